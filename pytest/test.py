@@ -1,3 +1,8 @@
+import asyncio
+import os
+import pathlib
+import tempfile
+import warnings
 import csx64
 
 def main():
@@ -15,15 +20,258 @@ main:
     ret
 '''
     obj = csx64.assemble(prog_name, prog)
+    assert not obj.warnings
+    assert obj.global_symbols == ['main'] and obj.extern_symbols == []
+    assert obj.labels() == [('main', 'text', 0)] and obj.segment_sizes['text'] > 0 and obj.segment_sizes['bss'] == 0
+    assert csx64.ObjectFile.from_bytes(obj.to_bytes()).to_bytes() == obj.to_bytes()
+    try:
+        csx64.ObjectFile.from_bytes(obj.to_bytes()[:-3])
+        assert False
+    except csx64.FormatError:
+        pass
     objs = csx64.stdlib()
     objs.append((prog_name, obj))
     exe = csx64.link(objs, ('start', 'main'))
+    text_start = next(addr for seg, addr, _ in exe.segments if seg == 'text')
+    assert text_start <= exe.entry_point < text_start + exe.text_size and len(exe.text) == exe.text_size
+    assert exe.memory_footprint == sum(size for _, _, size in exe.segments)
+    loaded = csx64.Executable.from_bytes(exe.to_bytes())
+    assert loaded == exe and hash(loaded) == hash(exe) and loaded.digest() == exe.digest() and loaded.entry_point == exe.entry_point
+    assert loaded.to_bytes() == exe.to_bytes() and loaded.text == exe.text
+    try:
+        csx64.Executable.from_bytes(obj.to_bytes())
+        assert False
+    except csx64.FormatError:
+        pass
     emu = csx64.Emulator()
     emu.init(exe)
     emu.ots = True
     stdin, stdout, stderr = emu.setup_stdio()
     _, state = emu.execute_cycles()
-    assert state == 'Terminated' and emu.get_state() == 'Terminated' and emu.get_return_value() == 9
+    with warnings.catch_warnings(record = True) as caught:
+        warnings.simplefilter('always')
+        assert state == 'Terminated' and emu.get_state() == 'Terminated' and emu.get_return_value() == 9
+    assert len(caught) == 2 and all(issubclass(w.category, DeprecationWarning) for w in caught)
+    assert state == csx64.StopKind.Terminated and emu.get_state() == csx64.State.Terminated
+    assert csx64.State.Terminated != csx64.StopKind.Terminated
+
+    emu.init(exe)
+    reason = emu.execute_cycles()
+    assert reason.kind is csx64.StopKind.Terminated and reason.exit_code == 9 and reason.error is None and reason.cycles == reason[0]
+
+    crash = csx64.assemble('crash.asm', 'global main\nsegment text\nmain:\n    mov rax, [0xffffffffff]\n    ret\n')
+    crash_exe = csx64.link(csx64.stdlib() + [('crash.asm', crash)], ('start', 'main'))
+    emu.init(crash_exe)
+    reason = emu.execute_cycles()
+    err = emu.get_error()
+    assert reason.kind is csx64.StopKind.Error and emu.get_state() is csx64.State.Error
+    assert isinstance(err, csx64.ExecErrorInfo) and err.kind == reason.error.kind and err.address == 0xffffffffff
+    emu.init(crash_exe)
+    try:
+        emu.execute_cycles(raise_on_error = True)
+        assert False
+    except csx64.MemoryAccessError as e:
+        assert isinstance(e, csx64.ExecutionError) and e.kind == err.kind and e.address == err.address and e.symbol.startswith('main')
+
+    obj2 = csx64.assemble(prog_name, prog.replace('mov esi, 4', 'mov esi, ADDEND'), predefines = { 'ADDEND': 4 })
+    emu.init(csx64.link(csx64.stdlib() + [(prog_name, obj2)], ('start', 'main')))
+    assert emu.execute_cycles().exit_code == 9
+    csx64.assemble(prog_name, prog, predefines = { 'MASK': 0xFFFFFFFFFFFFFFFF })
+    for bad in [True, 1 << 64]:
+        try:
+            csx64.assemble(prog_name, prog, predefines = { 'BAD': bad })
+            assert False
+        except (TypeError, ValueError):
+            pass
+
+    with tempfile.TemporaryDirectory() as src_dir:
+        path = src_dir + '/' + prog_name
+        with open(path, 'w') as f:
+            f.write(prog)
+        assert csx64.assemble_file(path) == csx64.assemble(path, prog)
+        assert csx64.assemble_file(pathlib.Path(path)) == csx64.assemble(path, prog)
+        with open(path, 'rb') as f:
+            assert csx64.assemble(prog_name, f) == obj
+    try:
+        csx64.assemble_file(prog_name + '.missing')
+        assert False
+    except OSError:
+        pass
+    try:
+        csx64.assemble(prog_name, prog.encode() + b'\n    db "\xff"\n')
+        assert False
+    except csx64.AssembleError as e:
+        assert e.file == prog_name and e.line == prog.count('\n') + 2
+    try:
+        csx64.assemble(prog_name, 42)
+        assert False
+    except TypeError:
+        pass
+
+    many = csx64.assemble_many([(prog_name, prog), ('demo2.asm', prog.encode())])
+    assert [name for name, _ in many] == [prog_name, 'demo2.asm']
+
+    inc = csx64.assemble('inc.asm', '%include "defs.inc"\nsegment text\n    mov eax, ADDEND\n', include_files = { 'defs.inc': 'ADDEND: equ 4\n' })
+    try:
+        csx64.assemble('inc.asm', '%include "outer.inc"\n', include_files = { 'outer.inc': 'segment text\n%include "missing.inc"\n' })
+        assert False
+    except csx64.AssembleError as e:
+        assert e.file == 'outer.inc' and e.line == 2 and e.include_chain == [('inc.asm', 1)]
+
+    with tempfile.TemporaryDirectory() as outer_dir:
+        inc_dir = outer_dir + '/inc'
+        os.mkdir(inc_dir)
+        for path in [inc_dir + '/defs.inc', outer_dir + '/secret.inc']:
+            with open(path, 'w') as f:
+                f.write('ADDEND: equ 4\n')
+        src = '%include "defs.inc"\nsegment text\n    mov eax, ADDEND\n'
+        assert csx64.assemble('inc.asm', src, include_path = [inc_dir]) == inc
+        for name, path in [('defs.inc', None), ('/etc/passwd', [inc_dir]), ('../secret.inc', [inc_dir])]:
+            try:
+                csx64.assemble('inc.asm', '%include "' + name + '"\n', include_path = path)
+                assert False
+            except csx64.AssembleError:
+                pass
+
+    macros = '%macro addto 2\n    add %1, %2\n%endmacro\n%rep 2\n    addto eax, N\n%endrep\n'
+    assert csx64.preprocess(macros, defines = { 'N': 3 }).split() == ['add', 'eax,', '3', 'add', 'eax,', '3']
+    csx64.assemble('macros.asm', 'segment text\n' + macros, preprocess = True, defines = { 'N': 3 })
+    scoped = '%macro clamp 1\n    cmp %1, 9\n    jle %%ok\n    mov %1, 9\n%%ok:\n%endmacro\nglobal main\nsegment text\nmain:\n    mov eax, 20\n.loop:\n    clamp eax\n    dec eax\n    jnz .loop\n    ret\n'
+    scoped_obj = csx64.assemble('scoped.asm', scoped, preprocess = True)
+    assert any(l.startswith('main.__macro') for l, _, _ in scoped_obj.labels()) and not scoped_obj.warnings
+    try:
+        csx64.preprocess(macros, listing = True)
+        assert False
+    except TypeError:
+        pass
+    guarded = '%ifdef USE_DEFS\n%include "missing.inc"\n%endif\n%include "defs.inc"\n'
+    assert csx64.preprocess(guarded, include_files = { 'defs.inc': 'ADDEND: equ 4\n' }).split() == ['ADDEND:', 'equ', '4']
+    for src in ['%rep 1000000000\n    nop\n%endrep\n', '%rep 1000000\n%rep 1000000\n%define X 1\n%endrep\n%endrep\n', '%macro twice 0\n    nop\n    nop\n%endmacro\n' + '%rep 1000\n    twice\n%endrep\n' * 600]:
+        try:
+            csx64.preprocess(src)
+            assert False
+        except csx64.AssembleError:
+            pass
+    for cond in ['(' * 100000 + '1' + ')' * 100000, '-' * 1000000 + '1']:
+        try:
+            csx64.preprocess('%if ' + cond + '\n%endif\n')
+            assert False
+        except csx64.AssembleError as e:
+            assert 'nested' in e.message
+    try:
+        csx64.assemble(prog_name, prog, no_such_option = True)
+        assert False
+    except TypeError:
+        pass
+
+    listed = csx64.assemble(prog_name, prog, listing = True)
+    assert listed == obj and listed != inc and len({ listed, obj, inc }) == 2
+    assert [l.segment for l in listed.listing() if l.bytes] == ['text'] * 5
+    print('\n'.join(str(l) for l in listed.listing()))
+    clash = 'segment text\n__listing:\n    ret\n'
+    csx64.assemble('clash.asm', clash)
+    try:
+        csx64.assemble('clash.asm', clash, listing = True)
+        assert False
+    except csx64.AssembleError as e:
+        assert e.kind == 'ListingError' and e.file == 'clash.asm'
+
+    mapped, link_map = csx64.link(objs, ('start', 'main'), map = True)
+    assert mapped == exe and ('main', exe.symbol_address('main'), prog_name) in link_map.symbols
+    for seg, _, seg_size in link_map.segments:
+        assert sum(size for _, s, _, size in link_map.contributions if s == seg) + sum(size for s, _, size in link_map.gaps if s == seg) == seg_size
+    print(link_map)
+
+    debug_exe = csx64.link(csx64.stdlib() + [(prog_name, csx64.assemble(prog_name, prog, debug = True))], ('start', 'main'))
+    addrs = debug_exe.addresses_for(prog_name, 6)
+    assert len(addrs) == 1 and debug_exe.source_location(addrs[0]) == (prog_name, 6)
+    main_addr = debug_exe.symbol_address('main')
+    assert main_addr == addrs[0] and debug_exe.symbolize(main_addr + 2) == 'main+0x2'
+    assert any(s.name == 'main' and s.object == prog_name and s.is_global for s in debug_exe.symbols())
+    bss_exe = csx64.link(csx64.stdlib() + [('bss.asm', csx64.assemble('bss.asm', 'global main, buf\nsegment text\nmain:\n    ret\nsegment bss\nbuf: resq 4\n'))], ('start', 'main'))
+    bss_start, bss_size = next((addr, size) for seg, addr, size in bss_exe.segments if seg == 'bss')
+    assert bss_start <= bss_exe.symbol_address('buf') < bss_start + bss_size
+
+    with tempfile.TemporaryDirectory() as cache_dir:
+        cache = csx64.BuildCache(cache_dir)
+        cached = [cache.assemble(prog_name, prog) for _ in range(2)]
+        try:
+            cache.assemble(prog_name, prog, no_such_option = True)
+            assert False
+        except TypeError:
+            pass
+        assert cached[0] == cached[1] == obj and (cache.hits, cache.misses) == (1, 1)
+        linked = [cache.link(csx64.stdlib() + [(prog_name, cached[0])], ('start', 'main')) for _ in range(2)]
+        assert linked[0] == linked[1] == exe and (cache.hits, cache.misses) == (2, 2)
+        gone = csx64.BuildCache(cache_dir + '/gone')
+        os.rmdir(cache_dir + '/gone')
+        assert gone.assemble(prog_name, prog) == obj and gone.misses == 1
+
+    exports = 'global ' + ', '.join('f%d' % i for i in range(16)) + '\nextern ' + ', '.join('g%d' % i for i in range(16)) + '\nsegment text\n'
+    exports += ''.join('f%d:\n    call g%d\n' % (i, i) for i in range(16))
+    exported = [csx64.assemble('exports.asm', exports) for _ in range(4)]
+    assert len({ o.to_bytes() for o in exported }) == 1 and len({ o.digest() for o in exported }) == 1 and len(set(exported)) == 1
+    assert csx64.ObjectFile.from_bytes(exported[0].to_bytes()).global_symbols == exported[0].global_symbols
+
+    helpers = csx64.Archive([
+        ('helper.asm', csx64.assemble('helper.asm', 'global helper\nsegment text\nhelper:\n    mov eax, 9\n    ret\n')),
+        ('other.asm', csx64.assemble('other.asm', 'global main\nsegment text\nmain:\n    ret\n')),
+    ])
+    helpers = csx64.Archive.from_bytes(helpers.to_bytes())
+    caller = csx64.assemble('caller.asm', 'global main\nextern helper\nsegment text\nmain:\n    call helper\n    ret\n')
+    emu.init(csx64.link(csx64.stdlib() + [('caller.asm', caller)], ('start', 'main'), archives = [helpers]))
+    assert len(helpers) == 2 and emu.execute_cycles().exit_code == 9
+
+    warned = csx64.assemble('warn.asm', 'segment text\nunused:\n    ret\n    nop\n')
+    assert sorted(w.code for w in warned.warnings) == ['unreachable-code', 'unused-label']
+    try:
+        csx64.assemble('warn.asm', '%include "warn.inc"\n', include_files = { 'warn.inc': 'segment text\nunused:\n    ret\n' }, warnings_as_errors = True)
+        assert False
+    except csx64.AssembleError as e:
+        assert e.kind == 'unused-label' and e.file == 'warn.inc' and e.include_chain == [('warn.asm', 1)] and e.column is None
+
+    try:
+        csx64.assemble('bad.asm', 'segment text\n    mov eax, \n')
+        assert False
+    except csx64.AssembleError as e:
+        assert e.file == 'bad.asm' and e.line is not None and e.kind and isinstance(e, RuntimeError)
+
+    try:
+        csx64.link([(prog_name, obj)], ('start', 'main'))
+        assert False
+    except csx64.LinkError as e:
+        assert e.entry_point_errors
+    try:
+        csx64.link([
+            ('a.asm', csx64.assemble('a.asm', 'global main\nextern missing\nsegment text\nmain:\n    call missing\n    ret\n')),
+            ('b.asm', csx64.assemble('b.asm', 'global main\nsegment text\nmain:\n    ret\n')),
+        ], None)
+        assert False
+    except csx64.LinkError as e:
+        assert isinstance(e, RuntimeError) and e.unresolved == [('missing', 'a.asm')] and e.duplicates == [('main', 'a.asm', 'b.asm')]
+
+    modules = csx64.stdlib_modules()
+    assert [m.name for m in modules] == [name for name, _ in csx64.stdlib()]
+    start = next(m for m in modules if m.name == 'start')
+    included = [name for name, _ in csx64.stdlib(include = ['start'])]
+    assert 'start' in included and set(start.dependencies) <= set(included)
+    assert 'start' not in [name for name, _ in csx64.stdlib(exclude = ['start'])]
+
+    res = csx64.run([(prog_name, prog)], stdin = b'ignored')
+    assert res.exit_code == 9 and res.state == csx64.State.Terminated and res.error is None and res.stdout == b'' and not res.timed_out
+    res = csx64.run([('loop.asm', 'global main\nsegment text\nmain:\n    jmp main\n')], max_cycles = 1000)
+    assert res.exit_code is None and res.state == csx64.State.Running and res.cycles == 1000
+    res = csx64.run([('loop.asm', 'global main\nsegment text\nmain:\n    jmp main\n')], timeout = 0.05)
+    assert res.timed_out and res.exit_code is None and res.state == csx64.State.Running and res.cycles > 0
+    hello = 'global main\nextern puts\nsegment text\nmain:\n    mov rdi, msg\n    call puts\n    xor eax, eax\n    ret\nsegment rodata\nmsg: db "hello", 0\n'
+    res = csx64.run([('hello.asm', hello)], stdin = bytearray(b'ignored'))
+    assert res.exit_code == 0 and res.stdout.startswith(b'hello') and res.stderr == b''
+    for bad in ['text stdin', 42]:
+        try:
+            csx64.run([(prog_name, prog)], stdin = bad)
+            assert False
+        except TypeError:
+            pass
 
     print(emu.rax, emu.raxi, emu.raxf, emu.flags, emu.cc_b)
     emu.rax = 12
@@ -36,5 +284,24 @@ main:
     emu.raxf = 12.43
     print(emu.rax, emu.raxi, emu.raxf, emu.flags, emu.cc_ge)
 
+    async def run():
+        return await emu.run_async(slice = 1)
+    emu.init(exe)
+    _, state = asyncio.run(run())
+    assert state == csx64.StopKind.Terminated and emu.get_return_value() == 9
+
+    emu.init(exe)
+    task = emu.run_async()
+    try:
+        while True:
+            next(task)
+    except StopIteration as e:
+        assert e.value.exit_code == 9
+    try:
+        next(task)
+        assert False
+    except StopIteration as e:
+        assert e.value is None
+
 if __name__ == '__main__':
     main()
\ No newline at end of file
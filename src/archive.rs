@@ -0,0 +1,43 @@
+//! Static library archives: bundles of objects which are only linked when they define a symbol something else needs.
+
+use std::collections::HashSet;
+
+use csx64::asm::ObjectFile;
+
+use crate::inspect;
+
+/// Picks the archive members needed to resolve the externs of `objs` (and the entry point), including externs of the
+/// members themselves. Returns their indices in archive order.
+pub fn select<'a>(objs: &[(&'a str, &'a ObjectFile)], members: &[(&'a str, &'a ObjectFile)], entry_point: Option<(&str, &'a str)>) -> Vec<usize> {
+    let mut defined: HashSet<&str> = objs.iter().flat_map(|(_, obj)| inspect::global_symbols(obj)).collect();
+    let mut needed: Vec<&str> = objs.iter().flat_map(|(_, obj)| inspect::extern_symbols(obj)).collect();
+    let mut pulled = vec![false; members.len()];
+
+    // the entry point's object and symbol count as outstanding too
+    let mut queue = vec![];
+    if let Some((source, target)) = entry_point {
+        needed.push(target);
+        if !objs.iter().any(|(name, _)| *name == source) {
+            queue.extend(members.iter().position(|(name, _)| *name == source));
+        }
+    }
+
+    loop {
+        for i in queue.drain(..) {
+            if !std::mem::replace(&mut pulled[i], true) {
+                defined.extend(inspect::global_symbols(members[i].1));
+                needed.extend(inspect::extern_symbols(members[i].1));
+            }
+        }
+        let sym = match needed.pop() {
+            Some(v) => v,
+            None => break,
+        };
+        if !defined.contains(sym) {
+            // only the first member defining a symbol is considered (as with `ar` archives) - anything still missing is left for the linker to report
+            queue.extend(members.iter().position(|(_, obj)| inspect::is_global(obj, sym)));
+        }
+    }
+
+    (0..members.len()).filter(|&i| pulled[i]).collect()
+}
@@ -0,0 +1,79 @@
+//! Sorting upstream error variants into the binding's exception hierarchy.
+//! The classifying matches are exhaustive (no catch-all arms) so that new upstream variants fail to compile until they are classified.
+
+use csx64::asm::AsmErrorKind;
+use csx64::exec::ExecError;
+
+/// Which `AssembleError` subclass an assembler error is raised as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AsmErrorClass {
+    Syntax,
+    UndefinedSymbol,
+    OperandSize,
+    Other,
+}
+
+/// Expands to an exhaustive match giving the variant name and class of each listed variant.
+/// `{ .. }` patterns match unit, tuple and struct variants alike, so payloads don't need to be spelled out.
+macro_rules! classify_variants {
+    ($value:expr, $ty:ident, $($class:expr => [$($variant:ident),* $(,)?]),* $(,)?) => {
+        match $value {
+            $($($ty::$variant { .. } => (stringify!($variant), $class),)*)*
+        }
+    };
+}
+
+/// Gets the variant name and exception class of an assembler error.
+pub fn asm_error(kind: &AsmErrorKind) -> (&'static str, AsmErrorClass) {
+    use AsmErrorClass::*;
+    classify_variants!(kind, AsmErrorKind,
+        Syntax => [
+            ExpectedString, IncompleteString, IncompleteEscape, InvalidEscape, InvalidNumericLiteral, NumericLiteralWithZeroPrefix,
+            InvalidSymbolName, ReservedSymbolName, LocalSymbolBeforeNonlocal, ExpectedExprTerm, ExpectedCommaBeforeToken,
+            UnrecognizedMacroInvocation, UnknownOp, MissingCloseParen, UnexpectedCloseParen, InvalidAddressExpr, ExpectedAddress,
+            ExtraContentAfterInstruction, ExpectedSegment, UnknownSegment, ExpectedCommaBetweenArgs, EmptyArgument,
+            TimesMissingCount, TimesUsedOnEmptyLine, TimesIterOutsideOfTimes, InvalidRegister,
+        ],
+        UndefinedSymbol => [
+            UndefinedSymbols, GlobalSymbolWasNotDefined, ExternSymbolAlsoDefined,
+        ],
+        OperandSize => [
+            OperandSizeMismatch, OperandSizeNotSupported, SizeSpecNotAllowed, AmbiguousOperandSize, ImmediateTooLarge,
+        ],
+        Other => [
+            ReadError, SymbolAlreadyDefined, LabelOutsideOfSegment, InstructionOutsideOfTextSegment, IllegalInCurrentSegment,
+            WriteOutsideOfSegment, ExprIllegalError, ArgsExprEvalFailure, TimesCountWasNegative, TimesCountTooLarge,
+            AlignValueNotPowerOfTwo, AssertionFailure, EquWithoutLabel, EquDefinedInvalidExpr,
+        ],
+    )
+}
+
+/// Which `ExecutionError` subclass an execution error is raised as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExecErrorClass {
+    MemoryAccess,
+    InvalidOpcode,
+    StackOverflow,
+    Syscall,
+    Other,
+}
+
+/// Gets the variant name and exception class of an execution error.
+pub fn exec_error(err: &ExecError) -> (&'static str, ExecErrorClass) {
+    use ExecErrorClass::*;
+    classify_variants!(err, ExecError,
+        MemoryAccess => [OutOfBounds, AccessViolation, UnalignedAccess, WriteToReadOnly, ExecuteNonText],
+        InvalidOpcode => [UnknownOp, InvalidOpEncoding, InvalidOperandSize, UnsupportedInstruction],
+        StackOverflow => [StackOverflow],
+        Syscall => [UnrecognizedSyscall, InvalidSyscallArgs, FdOutOfRange, FdNotInUse, FilePermissions, IOFailure, InsufficientFds],
+        Other => [ArithmeticError, DivideByZero, DivisionOverflow, Abort, NotImplemented, PrivilegeViolation, NoExecutable],
+    )
+}
+
+/// Gets the faulting address of a memory error (other errors don't have one).
+pub fn exec_error_address(err: &ExecError) -> Option<u64> {
+    match *err {
+        ExecError::OutOfBounds(addr) | ExecError::AccessViolation(addr) | ExecError::UnalignedAccess(addr) | ExecError::WriteToReadOnly(addr) | ExecError::ExecuteNonText(addr) => Some(addr),
+        _ => None,
+    }
+}
@@ -0,0 +1,162 @@
+//! Address-to-source line tables and symbol tables for linked executables.
+//! Object-relative offsets are relocated using the segment placements reported by the linker.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::inspect::{self, Placement, Segment};
+use crate::listing::Entry;
+use crate::serial::{self, Reader, Writer};
+
+/// A run of executable bytes produced by a single source line.
+#[derive(Clone)]
+pub struct LineRecord {
+    pub address: u64,
+    pub size: usize,
+    pub file: Arc<str>,
+    pub line: usize,
+}
+
+/// A label from one of the linked objects, relocated to its final address.
+#[derive(Clone)]
+pub struct SymbolRecord {
+    pub name: String,
+    pub address: u64,
+    pub segment: Segment,
+    /// Distance to the next label in the same segment of the same object (unknown for the last one).
+    pub size: Option<usize>,
+    pub object: Arc<str>,
+    pub global: bool,
+}
+
+/// Debug information for an executable: line table and symbol table, both sorted by address.
+#[derive(Default)]
+pub struct DebugInfo {
+    lines: Vec<LineRecord>,
+    symbols: Vec<SymbolRecord>,
+    by_name: HashMap<String, usize>,
+}
+
+/// An object which was passed to the linker: its name, contents, and line listing if it was assembled with debug info.
+pub struct LinkedObject<'a> {
+    pub name: &'a str,
+    pub obj: &'a csx64::asm::ObjectFile,
+    pub lines: Option<&'a [Entry]>,
+}
+
+impl DebugInfo {
+    /// Builds the debug info for an executable from its objects and where the linker placed them.
+    /// Only objects with a listing (i.e. assembled with `debug=True`) contribute to the line table.
+    pub fn build(objs: &[LinkedObject], placements: &[Option<Placement>]) -> Self {
+        let mut lines = vec![];
+        let mut symbols = vec![];
+
+        for (linked, placement) in objs.iter().zip(placements.iter()) {
+            let placement = match placement {
+                Some(v) => v,
+                None => continue, // the linker left this object out
+            };
+            for line in linked.lines.unwrap_or_default() {
+                if let (Some(seg), Some(offset), true) = (line.segment, line.offset, line.size != 0) {
+                    lines.push(LineRecord { address: placement[seg as usize] + offset as u64, size: line.size, file: line.file.as_str().into(), line: line.line });
+                }
+            }
+
+            let object: Arc<str> = linked.name.into();
+            let mut labels: Vec<(&str, Segment, usize)> = inspect::labels(linked.obj).collect();
+            labels.sort_by_key(|&(_, seg, offset)| (seg, offset));
+            for (i, &(name, seg, offset)) in labels.iter().enumerate() {
+                symbols.push(SymbolRecord {
+                    name: name.to_owned(),
+                    address: placement[seg as usize] + offset as u64,
+                    segment: seg,
+                    size: labels.get(i + 1).filter(|l| l.1 == seg).map(|l| l.2 - offset),
+                    object: object.clone(),
+                    global: inspect::is_global(linked.obj, name),
+                });
+            }
+        }
+
+        DebugInfo::new(lines, symbols)
+    }
+
+    fn new(mut lines: Vec<LineRecord>, mut symbols: Vec<SymbolRecord>) -> Self {
+        lines.sort_by_key(|r| r.address);
+        symbols.sort_by_key(|s| s.address);
+
+        // globals take precedence, otherwise the first definition wins
+        let mut by_name: HashMap<String, usize> = HashMap::new();
+        for (i, sym) in symbols.iter().enumerate() {
+            match by_name.get(&sym.name) {
+                Some(&j) if symbols[j].global || !sym.global => (),
+                _ => { by_name.insert(sym.name.clone(), i); }
+            }
+        }
+
+        DebugInfo { lines, symbols, by_name }
+    }
+
+    pub fn write(&self, w: &mut Writer) {
+        w.seq(&self.lines, |w, r| {
+            w.u64(r.address);
+            w.usize(r.size);
+            w.str(&r.file);
+            w.usize(r.line);
+        });
+        w.seq(&self.symbols, |w, s| {
+            w.str(&s.name);
+            w.u64(s.address);
+            w.segment(s.segment);
+            w.opt(s.size, Writer::usize);
+            w.str(&s.object);
+            w.u8(s.global as u8);
+        });
+    }
+    pub fn read(r: &mut Reader) -> Result<Self, serial::Error> {
+        let lines = r.seq(|r| Ok(LineRecord { address: r.u64()?, size: r.usize()?, file: r.str()?.into(), line: r.usize()? }))?;
+        let symbols = r.seq(|r| Ok(SymbolRecord {
+            name: r.str()?,
+            address: r.u64()?,
+            segment: r.segment()?,
+            size: r.opt(Reader::usize)?,
+            object: r.str()?.into(),
+            global: r.u8()? != 0,
+        }))?;
+        Ok(DebugInfo::new(lines, symbols))
+    }
+
+    /// Gets the source line which produced the byte at `addr`.
+    pub fn line_at(&self, addr: u64) -> Option<&LineRecord> {
+        let i = self.lines.partition_point(|r| r.address <= addr).checked_sub(1)?;
+        let r = &self.lines[i];
+        (addr < r.address + r.size as u64).then_some(r)
+    }
+
+    /// Gets the start address of every run of bytes produced by the given source line.
+    pub fn addresses_for(&self, file: &str, line: usize) -> Vec<u64> {
+        self.lines.iter().filter(|r| r.line == line && &*r.file == file).map(|r| r.address).collect()
+    }
+
+    pub fn symbols(&self) -> &[SymbolRecord] {
+        &self.symbols
+    }
+    pub fn symbol(&self, name: &str) -> Option<&SymbolRecord> {
+        self.by_name.get(name).map(|&i| &self.symbols[i])
+    }
+
+    /// Gets the nearest symbol at or before `addr` (preferring globals when several share an address).
+    pub fn symbol_before(&self, addr: u64) -> Option<&SymbolRecord> {
+        let end = self.symbols.partition_point(|s| s.address <= addr);
+        let last = self.symbols[..end].last()?;
+        self.symbols[..end].iter().rev().take_while(|s| s.address == last.address).find(|s| s.global).or(Some(last))
+    }
+
+    /// Formats `addr` as `symbol+0xoffset` (or just hex if there is no symbol before it).
+    pub fn symbolize(&self, addr: u64) -> String {
+        match self.symbol_before(addr) {
+            Some(s) if s.address == addr => s.name.clone(),
+            Some(s) => format!("{}+{:#x}", s.name, addr - s.address),
+            None => format!("{:#x}", addr),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Read-only views into the contents of `csx64` object files and executables.
+//! Everything which reaches into the upstream representation lives here so the rest of the binding only sees plain data.
+
+use csx64::asm::{AsmSegment, LinkError, ObjectFile};
+use csx64::common::Executable;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Segment {
+    Text,
+    Rodata,
+    Data,
+    Bss,
+}
+impl Segment {
+    pub const ALL: [Segment; 4] = [Segment::Text, Segment::Rodata, Segment::Data, Segment::Bss];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Segment::Text => "text",
+            Segment::Rodata => "rodata",
+            Segment::Data => "data",
+            Segment::Bss => "bss",
+        }
+    }
+}
+impl From<AsmSegment> for Segment {
+    fn from(seg: AsmSegment) -> Self {
+        match seg {
+            AsmSegment::Text => Segment::Text,
+            AsmSegment::Rodata => Segment::Rodata,
+            AsmSegment::Data => Segment::Data,
+            AsmSegment::Bss => Segment::Bss,
+        }
+    }
+}
+
+/// A location in an object file which the linker will patch (e.g. a reference to an extern or to another segment).
+pub struct Hole {
+    pub segment: Segment,
+    pub offset: usize,
+    pub size: usize,
+    pub line: usize,
+}
+
+pub fn global_symbols(obj: &ObjectFile) -> impl Iterator<Item = &str> {
+    obj.global_symbols.iter().map(String::as_str)
+}
+pub fn extern_symbols(obj: &ObjectFile) -> impl Iterator<Item = &str> {
+    obj.extern_symbols.iter().map(String::as_str)
+}
+pub fn is_global(obj: &ObjectFile, name: &str) -> bool {
+    obj.global_symbols.contains(name)
+}
+
+/// Removes the global and extern symbol sets from an object, returning them sorted.
+pub fn take_symbol_sets(obj: &mut ObjectFile) -> (Vec<String>, Vec<String>) {
+    let mut globals: Vec<String> = std::mem::take(&mut obj.global_symbols).into_iter().collect();
+    let mut externs: Vec<String> = std::mem::take(&mut obj.extern_symbols).into_iter().collect();
+    globals.sort_unstable();
+    externs.sort_unstable();
+    (globals, externs)
+}
+/// Puts back symbol sets removed by [`take_symbol_sets`].
+pub fn restore_symbol_sets(obj: &mut ObjectFile, globals: Vec<String>, externs: Vec<String>) {
+    obj.global_symbols = globals.into_iter().collect();
+    obj.extern_symbols = externs.into_iter().collect();
+}
+
+/// The emitted bytes of a segment (empty for bss, which has no content).
+pub fn segment_content(obj: &ObjectFile, seg: Segment) -> &[u8] {
+    match seg {
+        Segment::Text => obj.text_segment(),
+        Segment::Rodata => obj.rodata_segment(),
+        Segment::Data => obj.data_segment(),
+        Segment::Bss => &[],
+    }
+}
+pub fn segment_len(obj: &ObjectFile, seg: Segment) -> usize {
+    match seg {
+        Segment::Bss => obj.bss_len(),
+        _ => segment_content(obj, seg).len(),
+    }
+}
+
+/// Every label defined by the object file along with its segment and offset within that segment.
+pub fn labels(obj: &ObjectFile) -> impl Iterator<Item = (&str, Segment, usize)> + '_ {
+    obj.label_offsets().map(|(name, seg, offset)| (name, seg.into(), offset))
+}
+
+pub fn holes(obj: &ObjectFile) -> impl Iterator<Item = Hole> + '_ {
+    obj.holes().map(|(seg, offset, size, line)| Hole { segment: seg.into(), offset, size, line })
+}
+
+/// Contents of an executable segment as laid out in memory (empty for bss).
+pub fn exe_segment_content(exe: &Executable, seg: Segment) -> &[u8] {
+    let start = exe_segment_start(exe, seg) as usize;
+    match seg {
+        Segment::Bss => &[],
+        _ => &exe.content()[start..start + exe_segment_len(exe, seg)],
+    }
+}
+/// Checks that the segment lengths recorded in an executable agree with its content (which the accessors above rely on).
+pub fn exe_is_consistent(exe: &Executable) -> bool {
+    [Segment::Text, Segment::Rodata, Segment::Data].iter().map(|&seg| exe_segment_len(exe, seg)).sum::<usize>() == exe.content().len()
+}
+pub fn exe_segment_len(exe: &Executable, seg: Segment) -> usize {
+    match seg {
+        Segment::Text => exe.text_seglen(),
+        Segment::Rodata => exe.rodata_seglen(),
+        Segment::Data => exe.data_seglen(),
+        Segment::Bss => exe.bss_seglen(),
+    }
+}
+/// The address a segment is loaded at (executables are loaded at address 0 with segments in order).
+pub fn exe_segment_start(exe: &Executable, seg: Segment) -> u64 {
+    Segment::ALL.iter().take_while(|&&s| s != seg).map(|&s| exe_segment_len(exe, s) as u64).sum()
+}
+
+/// Load address of each segment of an object, indexed by `Segment as usize`.
+pub type Placement = [u64; 4];
+
+/// Where the linker put each object, in the order the objects were given (`None` for objects it didn't include).
+pub struct Layout {
+    pub objects: Vec<Option<Placement>>,
+    pub entry_point: u64,
+}
+
+/// Links the objects (without copying them), also returning where each of them was placed.
+pub fn link(objs: &[(&str, &ObjectFile)], entry_point: Option<(&str, &str)>) -> Result<(Executable, Layout), LinkError> {
+    let (exe, layout) = csx64::asm::link(objs, entry_point)?;
+    let objects = layout.objects.iter().map(|o| o.as_ref().map(|o| [o.text_base, o.rodata_base, o.data_base, o.bss_base])).collect();
+    Ok((exe, Layout { objects, entry_point: layout.entry_point }))
+}
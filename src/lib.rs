@@ -1,15 +1,522 @@
-use std::sync::{Arc, Mutex};
+mod archive;
+mod classify;
+mod debuginfo;
+mod inspect;
+mod linkmap;
+mod lint;
+mod listing;
+mod preprocess;
+mod serial;
+mod stdlib;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
 use pyo3::prelude::*;
-use pyo3::exceptions::PyRuntimeError;
-use pyo3::types::PyBytes;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::types::{PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyLong, PyString};
+use pyo3::{PyIterProtocol, PyAsyncProtocol, PyObjectProtocol, PySequenceProtocol};
+use pyo3::exceptions::{PyIndexError, PyStopIteration};
+use pyo3::class::iter::IterNextOutput;
+use pyo3::class::basic::CompareOp;
+use pyo3::once_cell::GILOnceCell;
+
+// raised by assemble - instances carry file, line, column, kind and message attributes
+create_exception!(csx64, AssembleError, PyRuntimeError);
+create_exception!(csx64, AsmSyntaxError, AssembleError);
+create_exception!(csx64, UndefinedSymbolError, AssembleError);
+create_exception!(csx64, OperandSizeError, AssembleError);
+
+/// Attaches the given attributes to the exception instance of `err`.
+fn with_attrs(py: Python, err: PyErr, attrs: &[(&str, PyObject)]) -> PyErr {
+    let inst = err.instance(py);
+    for (name, value) in attrs {
+        if let Err(e) = inst.setattr(*name, value) {
+            return e;
+        }
+    }
+    err
+}
+
+fn include_chain(origin: &preprocess::Origin) -> Vec<(String, usize)> {
+    origin.chain.iter().map(|(f, l)| (f.to_string(), *l)).collect()
+}
+
+/// Attaches the attributes every `AssembleError` carries.
+#[allow(clippy::too_many_arguments)]
+fn assemble_error_attrs(py: Python, err: PyErr, file: &str, line: Option<usize>, column: Option<usize>, kind: &str, message: &str, chain: &[(String, usize)]) -> PyErr {
+    with_attrs(py, err, &[
+        ("file", file.into_py(py)),
+        ("line", line.into_py(py)),
+        ("column", column.into_py(py)),
+        ("kind", kind.into_py(py)),
+        ("message", message.into_py(py)),
+        ("include_chain", chain.to_vec().into_py(py)),
+    ])
+}
+
+fn assemble_error(py: Python, e: csx64::asm::AsmError, src: &preprocess::Expanded) -> PyErr {
+    let origin = &src.origin(e.line_num);
+    let (kind, class) = classify::asm_error(&e.kind);
+    let msg = format!("{}", e);
+    let err = match class {
+        classify::AsmErrorClass::Syntax => AsmSyntaxError::new_err(msg.clone()),
+        classify::AsmErrorClass::UndefinedSymbol => UndefinedSymbolError::new_err(msg.clone()),
+        classify::AsmErrorClass::OperandSize => OperandSizeError::new_err(msg.clone()),
+        classify::AsmErrorClass::Other => AssembleError::new_err(msg.clone()),
+    };
+
+    assemble_error_attrs(py, err, &origin.file, Some(origin.line), Some(e.pos), kind, &msg, &include_chain(origin))
+}
+/// Lines of the instrumented source don't correspond to the original, so only the file is reported.
+fn listing_error(py: Python, e: csx64::asm::AsmError, file: &str) -> PyErr {
+    let msg = format!("failed to build listing: {}", e);
+    assemble_error_attrs(py, AssembleError::new_err(format!("{}: {}", file, msg)), file, None, None, "ListingError", &msg, &[])
+}
+fn preprocess_error(py: Python, e: preprocess::Error) -> PyErr {
+    let err = AssembleError::new_err(format!("{}:{}: {}", e.origin.file, e.origin.line, e.message));
+    assemble_error_attrs(py, err, &e.origin.file, Some(e.origin.line), None, "PreprocessError", &e.message, &include_chain(&e.origin))
+}
+
+// raised by link - instances carry unresolved, duplicates and entry_point_errors attributes
+create_exception!(csx64, LinkError, PyRuntimeError);
+
+/// Collects every unresolved extern, duplicate global, and entry point problem among the given objects.
+/// The underlying linker stops at the first problem, so this is only run after it has already failed.
+fn link_error(py: Python, objs: &[(String, Py<ObjectFile>)], entry_point: Option<(&str, &str)>, e: csx64::asm::LinkError) -> PyErr {
+    let objs: Vec<(&str, PyRef<ObjectFile>)> = objs.iter().map(|(name, obj)| (name.as_str(), obj.borrow(py))).collect();
+
+    let mut definers: HashMap<&str, &str> = HashMap::new();
+    let mut duplicates: Vec<(&str, &str, &str)> = vec![];
+    for (name, obj) in objs.iter() {
+        for sym in inspect::global_symbols(&obj.0) {
+            match definers.get(sym) {
+                Some(first) => duplicates.push((sym, *first, *name)),
+                None => { definers.insert(sym, *name); }
+            }
+        }
+    }
+
+    let mut unresolved: Vec<(&str, &str)> = vec![];
+    for (name, obj) in objs.iter() {
+        for sym in inspect::extern_symbols(&obj.0) {
+            if !definers.contains_key(sym) {
+                unresolved.push((sym, *name));
+            }
+        }
+    }
+
+    let mut entry_point_errors: Vec<String> = vec![];
+    if let Some((source, target)) = entry_point {
+        if !objs.iter().any(|(name, _)| *name == source) {
+            entry_point_errors.push(format!("entry point source '{}' is not one of the linked objects", source));
+        }
+        if !definers.contains_key(target) {
+            entry_point_errors.push(format!("entry point target '{}' is not a global symbol of any linked object", target));
+        }
+    }
 
+    with_attrs(py, LinkError::new_err(format!("{}", e)), &[
+        ("unresolved", unresolved.into_py(py)),
+        ("duplicates", duplicates.into_py(py)),
+        ("entry_point_errors", entry_point_errors.into_py(py)),
+    ])
+}
+
+// raised when loading a corrupt, truncated, or incompatible object file or executable
+create_exception!(csx64, FormatError, PyValueError);
+
+// raised by execute_cycles(raise_on_error = True) - instances carry the ExecErrorInfo as info
+create_exception!(csx64, ExecutionError, PyException);
+create_exception!(csx64, MemoryAccessError, ExecutionError);
+create_exception!(csx64, InvalidOpcodeError, ExecutionError);
+create_exception!(csx64, StackOverflowError, ExecutionError);
+create_exception!(csx64, SyscallError, ExecutionError);
+
+/// Structured form of an execution error (`csx64::exec::ExecError`).
 #[derive(Clone)]
 #[pyclass]
-struct ObjectFile(csx64::asm::ObjectFile);
+struct ExecErrorInfo {
+    #[pyo3(get)] kind: &'static str,
+    class: classify::ExecErrorClass,
+    #[pyo3(get)] message: String,
+    #[pyo3(get)] address: Option<u64>,
+    #[pyo3(get)] rip: u64,
+    /// `rip` as `symbol+offset`
+    #[pyo3(get)] symbol: String,
+}
+impl ExecErrorInfo {
+    fn new(emu: &Emulator, err: &csx64::exec::ExecError) -> Self {
+        let (kind, class) = classify::exec_error(err);
+        let rip = emu.0.instruction_pointer;
+        ExecErrorInfo { kind, class, message: format!("{:?}", err), address: classify::exec_error_address(err), rip, symbol: emu.1.symbolize(rip) }
+    }
+    fn to_exception(&self, py: Python) -> PyErr {
+        let err = match self.class {
+            classify::ExecErrorClass::MemoryAccess => MemoryAccessError::new_err(self.message.clone()),
+            classify::ExecErrorClass::InvalidOpcode => InvalidOpcodeError::new_err(self.message.clone()),
+            classify::ExecErrorClass::StackOverflow => StackOverflowError::new_err(self.message.clone()),
+            classify::ExecErrorClass::Syscall => SyscallError::new_err(self.message.clone()),
+            classify::ExecErrorClass::Other => ExecutionError::new_err(self.message.clone()),
+        };
+        with_attrs(py, err, &[
+            ("kind", self.kind.into_py(py)),
+            ("address", self.address.into_py(py)),
+            ("rip", self.rip.into_py(py)),
+            ("symbol", self.symbol.clone().into_py(py)),
+            ("info", self.clone().into_py(py)),
+        ])
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for ExecErrorInfo {
+    fn __str__(&self) -> String { self.message.clone() }
+    fn __repr__(&self) -> String {
+        format!("ExecErrorInfo(kind={:?}, address={:?}, rip={:#x} ({}))", self.kind, self.address.map(|a| format!("{:#x}", a)), self.rip, self.symbol)
+    }
+}
+
+/// A suspicious (but valid) construct found while assembling.
+#[derive(Clone)]
 #[pyclass]
-struct Executable(csx64::common::Executable);
+struct AsmWarning {
+    #[pyo3(get)] code: &'static str,
+    #[pyo3(get)] message: String,
+    #[pyo3(get)] file: String,
+    #[pyo3(get)] line: usize,
+    /// The `%include` sites leading to `file` as `(file, line)`, outermost first.
+    #[pyo3(get)] include_chain: Vec<(String, usize)>,
+}
+
+impl AsmWarning {
+    fn write(&self, w: &mut serial::Writer) {
+        w.str(self.code);
+        w.str(&self.message);
+        w.str(&self.file);
+        w.usize(self.line);
+        w.seq(&self.include_chain, |w, (file, line)| { w.str(file); w.usize(*line) });
+    }
+    fn read(r: &mut serial::Reader) -> Result<Self, serial::Error> {
+        let code = r.str()?;
+        let code = *lint::CODES.iter().find(|&&c| c == code).ok_or_else(|| serial::Error::Corrupt(format!("unknown warning code '{}'", code)))?;
+        Ok(AsmWarning { code, message: r.str()?, file: r.str()?, line: r.usize()?, include_chain: r.seq(|r| Ok((r.str()?, r.usize()?)))? })
+    }
+}
+impl std::fmt::Display for AsmWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}: warning: {} [{}]", self.file, self.line, self.message, self.code)
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for AsmWarning {
+    fn __str__(&self) -> String { self.to_string() }
+    fn __repr__(&self) -> String { format!("AsmWarning(code={:?}, file={:?}, line={})", self.code, self.file, self.line) }
+}
+
+/// One source line of an assembly listing.
+#[derive(Clone)]
 #[pyclass]
-struct Emulator(csx64::exec::Emulator);
+struct ListingLine {
+    #[pyo3(get)] file: String,
+    #[pyo3(get)] line: usize,
+    #[pyo3(get)] segment: Option<&'static str>,
+    #[pyo3(get)] offset: Option<usize>,
+    #[pyo3(get)] size: usize,
+    bytes: Vec<u8>,
+    #[pyo3(get)] relocations: Vec<(usize, usize)>,
+    #[pyo3(get)] text: String,
+}
+impl From<&listing::Entry> for ListingLine {
+    fn from(e: &listing::Entry) -> Self {
+        ListingLine {
+            file: e.file.clone(),
+            line: e.line,
+            segment: e.segment.map(inspect::Segment::name),
+            offset: e.offset,
+            size: e.size,
+            bytes: e.bytes.clone(),
+            relocations: e.relocations.clone(),
+            text: e.text.clone(),
+        }
+    }
+}
+
+#[pymethods]
+impl ListingLine {
+    #[getter] fn bytes(&self, py: Python) -> PyObject { PyBytes::new(py, &self.bytes).into() }
+}
+#[pyproto]
+impl PyObjectProtocol for ListingLine {
+    fn __str__(&self) -> String {
+        let pos = match (self.segment, self.offset) {
+            (Some(seg), Some(off)) => format!("{:>6}:{:08x}", seg, off),
+            _ => " ".repeat(15),
+        };
+        // patched bytes are shown as ?? since their final value is only known after linking
+        let bytes: Vec<String> = self.bytes.iter().enumerate().map(|(i, b)| {
+            let patched = self.offset.map(|off| self.relocations.iter().any(|&(r, size)| (r..r + size).contains(&(off + i)))).unwrap_or(false);
+            if patched { "??".into() } else { format!("{:02x}", b) }
+        }).collect();
+        format!("{:>5} {} {:<24} {}", self.line, pos, bytes.join(""), self.text)
+    }
+}
+
+/// Extra information about an object file gathered by the binding (not part of the csx64 object format).
+#[derive(Clone, Default)]
+struct ObjectMeta {
+    warnings: Vec<AsmWarning>,
+    /// Per-line listing, kept when assembled with `listing=True` or `debug=True` (it doubles as the line table).
+    listing: Option<Arc<Vec<listing::Entry>>>,
+}
+impl ObjectMeta {
+    fn write(&self, w: &mut serial::Writer) {
+        w.seq(&self.warnings, |w, v| v.write(w));
+        w.opt(self.listing.as_deref(), |w, v| w.seq(v, |w, e| e.write(w)));
+    }
+    fn read(r: &mut serial::Reader) -> Result<Self, serial::Error> {
+        Ok(ObjectMeta {
+            warnings: r.seq(AsmWarning::read)?,
+            listing: r.opt(|r| r.seq(listing::Entry::read))?.map(Arc::new),
+        })
+    }
+}
+
+#[derive(Clone)]
+#[pyclass]
+struct ObjectFile(Arc<csx64::asm::ObjectFile>, ObjectMeta, Arc<OnceLock<[u8; 32]>>);
+
+impl ObjectFile {
+    fn new(obj: Arc<csx64::asm::ObjectFile>, meta: ObjectMeta) -> Self {
+        ObjectFile(obj, meta, Default::default())
+    }
+    /// Writes the object in canonical form: the upstream encoding without its symbol sets, then the sets in sorted order.
+    /// Upstream writes hash sets in iteration order, which differs from run to run.
+    fn write_canonical(&self, w: &mut serial::Writer) {
+        let mut stripped = (*self.0).clone();
+        let (globals, externs) = inspect::take_symbol_sets(&mut stripped);
+        w.bytes(&serial::payload(&stripped));
+        w.seq(&globals, |w, s| w.str(s));
+        w.seq(&externs, |w, s| w.str(s));
+    }
+    /// Digest of the canonical form (computed on first use and shared by clones).
+    fn digest_raw(&self) -> [u8; 32] {
+        *self.2.get_or_init(|| serial::digest_with(|w| self.write_canonical(w)))
+    }
+    fn encode(&self) -> Vec<u8> {
+        serial::write_with(serial::Kind::Object, |w| {
+            self.write_canonical(w);
+            self.1.write(w);
+        })
+    }
+    fn decode(data: &[u8]) -> Result<Self, serial::Error> {
+        let mut r = serial::read_header(serial::Kind::Object, data)?;
+        let mut obj = serial::read_payload(&mut r)?;
+        let globals = r.seq(serial::Reader::str)?;
+        let externs = r.seq(serial::Reader::str)?;
+        inspect::restore_symbol_sets(&mut obj, globals, externs);
+        let meta = ObjectMeta::read(&mut r)?;
+        r.finish()?;
+        Ok(ObjectFile::new(Arc::new(obj), meta))
+    }
+}
+
+#[pymethods]
+impl ObjectFile {
+    #[getter] fn warnings(&self) -> Vec<AsmWarning> { self.1.warnings.clone() }
+    #[getter] fn global_symbols(&self) -> Vec<&str> {
+        let mut res: Vec<&str> = inspect::global_symbols(&self.0).collect();
+        res.sort_unstable();
+        res
+    }
+    #[getter] fn extern_symbols(&self) -> Vec<&str> {
+        let mut res: Vec<&str> = inspect::extern_symbols(&self.0).collect();
+        res.sort_unstable();
+        res
+    }
+    /// Gets every label defined in the object as `(name, segment, offset)`, ordered by position.
+    fn labels(&self) -> Vec<(&str, &'static str, usize)> {
+        let mut res: Vec<_> = inspect::labels(&self.0).collect();
+        res.sort_by_key(|&(name, seg, offset)| (seg, offset, name));
+        res.into_iter().map(|(name, seg, offset)| (name, seg.name(), offset)).collect()
+    }
+    #[getter] fn segment_sizes(&self) -> HashMap<&'static str, usize> {
+        inspect::Segment::ALL.iter().map(|&seg| (seg.name(), inspect::segment_len(&self.0, seg))).collect()
+    }
+    /// Gets the locations the linker still has to patch as `(segment, offset, size, line)`.
+    fn holes(&self) -> Vec<(&'static str, usize, usize, usize)> {
+        inspect::holes(&self.0).map(|h| (h.segment.name(), h.offset, h.size, h.line)).collect()
+    }
+
+    fn listing(&self) -> PyResult<Vec<ListingLine>> {
+        match &self.1.listing {
+            Some(v) => Ok(v.iter().map(Into::into).collect()),
+            None => Err(PyRuntimeError::new_err("no listing available (pass listing=True to assemble)")),
+        }
+    }
+
+    /// Serializes the object (along with its warnings and listing) in a versioned binary format.
+    fn to_bytes(&self, py: Python) -> PyObject {
+        PyBytes::new(py, &self.encode()).into()
+    }
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        ObjectFile::decode(data).map_err(|e| FormatError::new_err(e.to_string()))
+    }
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        Ok(std::fs::write(path, self.encode())?)
+    }
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        let data = std::fs::read(&path)?;
+        ObjectFile::decode(&data).map_err(|e| FormatError::new_err(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Gets the hex SHA-256 of the canonical serialized form (equal objects have equal digests).
+    fn digest(&self) -> String {
+        serial::hex(&self.digest_raw())
+    }
+}
+#[pyproto]
+impl PyObjectProtocol for ObjectFile {
+    /// Structural equality - binding metadata (warnings and listing) is not compared.
+    fn __richcmp__(&self, other: PyRef<ObjectFile>, op: CompareOp) -> PyObject {
+        let py = other.py();
+        match op {
+            CompareOp::Eq => (self.digest_raw() == other.digest_raw()).into_py(py),
+            CompareOp::Ne => (self.digest_raw() != other.digest_raw()).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+    fn __hash__(&self) -> isize {
+        serial::hash(&self.digest_raw())
+    }
+}
+/// A symbol in a linked executable.
+#[pyclass]
+struct Symbol {
+    #[pyo3(get)] name: String,
+    #[pyo3(get)] address: u64,
+    #[pyo3(get)] segment: &'static str,
+    #[pyo3(get)] size: Option<usize>,
+    #[pyo3(get)] object: String,
+    #[pyo3(get)] is_global: bool,
+}
+impl From<&debuginfo::SymbolRecord> for Symbol {
+    fn from(s: &debuginfo::SymbolRecord) -> Self {
+        Symbol { name: s.name.clone(), address: s.address, segment: s.segment.name(), size: s.size, object: s.object.to_string(), is_global: s.global }
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for Symbol {
+    fn __repr__(&self) -> String {
+        format!("Symbol(name={:?}, address={:#x}, segment={:?}, size={:?}, object={:?})", self.name, self.address, self.segment, self.size, self.object)
+    }
+}
+
+/// A linked executable along with its debug info and the entry point address reported by the linker.
+#[pyclass]
+struct Executable(csx64::common::Executable, Arc<debuginfo::DebugInfo>, u64);
+
+impl Executable {
+    fn encode(&self) -> Vec<u8> {
+        serial::write(serial::Kind::Executable, &self.0, |w| { w.u64(self.2); self.1.write(w) })
+    }
+    fn decode(data: &[u8]) -> Result<Self, serial::Error> {
+        let (exe, mut r) = serial::read(serial::Kind::Executable, data)?;
+        if !inspect::exe_is_consistent(&exe) {
+            return Err(serial::Error::Corrupt("segment lengths do not match the executable content".into()));
+        }
+        let entry_point = r.u64()?;
+        let info = debuginfo::DebugInfo::read(&mut r)?;
+        r.finish()?;
+        Ok(Executable(exe, Arc::new(info), entry_point))
+    }
+}
+
+#[pymethods]
+impl Executable {
+    /// Gets the `(file, line)` which produced the byte at `addr` (requires objects assembled with `debug=True`).
+    fn source_location(&self, addr: u64) -> Option<(String, usize)> {
+        self.1.line_at(addr).map(|r| (r.file.to_string(), r.line))
+    }
+    fn addresses_for(&self, file: &str, line: usize) -> Vec<u64> {
+        self.1.addresses_for(file, line)
+    }
+
+    fn symbols(&self) -> Vec<Symbol> {
+        self.1.symbols().iter().map(Into::into).collect()
+    }
+    fn symbol_address(&self, name: &str) -> Option<u64> {
+        self.1.symbol(name).map(|s| s.address)
+    }
+    fn symbolize(&self, addr: u64) -> String {
+        self.1.symbolize(addr)
+    }
+
+    #[getter] fn text_size(&self) -> usize { inspect::exe_segment_len(&self.0, inspect::Segment::Text) }
+    #[getter] fn rodata_size(&self) -> usize { inspect::exe_segment_len(&self.0, inspect::Segment::Rodata) }
+    #[getter] fn data_size(&self) -> usize { inspect::exe_segment_len(&self.0, inspect::Segment::Data) }
+    #[getter] fn bss_size(&self) -> usize { inspect::exe_segment_len(&self.0, inspect::Segment::Bss) }
+
+    #[getter] fn text(&self, py: Python) -> PyObject { PyBytes::new(py, inspect::exe_segment_content(&self.0, inspect::Segment::Text)).into() }
+    #[getter] fn rodata(&self, py: Python) -> PyObject { PyBytes::new(py, inspect::exe_segment_content(&self.0, inspect::Segment::Rodata)).into() }
+    #[getter] fn data(&self, py: Python) -> PyObject { PyBytes::new(py, inspect::exe_segment_content(&self.0, inspect::Segment::Data)).into() }
+
+    /// Address execution begins at (as resolved by the linker).
+    #[getter] fn entry_point(&self) -> u64 { self.2 }
+    /// Total size of all segments once loaded (not including the stack, which is set up by `Emulator.init`).
+    #[getter] fn memory_footprint(&self) -> u64 {
+        inspect::Segment::ALL.iter().map(|&seg| inspect::exe_segment_len(&self.0, seg) as u64).sum()
+    }
+    /// Gets the memory layout as a list of `(segment, address, size)`.
+    #[getter] fn segments(&self) -> Vec<(&'static str, u64, usize)> {
+        inspect::Segment::ALL.iter().map(|&seg| (seg.name(), inspect::exe_segment_start(&self.0, seg), inspect::exe_segment_len(&self.0, seg))).collect()
+    }
+
+    /// Serializes the executable (along with its debug info) in a versioned binary format.
+    fn to_bytes(&self, py: Python) -> PyObject {
+        PyBytes::new(py, &self.encode()).into()
+    }
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        Executable::decode(data).map_err(|e| FormatError::new_err(e.to_string()))
+    }
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        Ok(std::fs::write(path, self.encode())?)
+    }
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        let data = std::fs::read(&path)?;
+        Executable::decode(&data).map_err(|e| FormatError::new_err(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Gets the hex SHA-256 of the canonical serialized form (equal executables have equal digests).
+    fn digest(&self) -> String {
+        serial::hex(&serial::digest(&self.0))
+    }
+}
+#[pyproto]
+impl PyObjectProtocol for Executable {
+    /// Structural equality - binding metadata (debug info) is not compared.
+    fn __richcmp__(&self, other: PyRef<Executable>, op: CompareOp) -> PyObject {
+        let py = other.py();
+        match op {
+            CompareOp::Eq => (serial::payload(&self.0) == serial::payload(&other.0)).into_py(py),
+            CompareOp::Ne => (serial::payload(&self.0) != serial::payload(&other.0)).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+    fn __hash__(&self) -> isize {
+        serial::hash(&serial::digest(&self.0))
+    }
+}
+/// The emulator along with the debug info of the executable it was initialized with (used to symbolize errors).
+#[pyclass]
+struct Emulator(csx64::exec::Emulator, Arc<debuginfo::DebugInfo>);
 #[pyclass]
 struct MemoryFile(Arc<Mutex<csx64::exec::fs::MemoryFile>>);
 
@@ -20,11 +527,180 @@ impl MemoryFile {
     }
 }
 
+type SharedFile = Arc<Mutex<csx64::exec::fs::MemoryFile>>;
+
+/// Replaces the emulator's stdio with memory files. Without `input`, stdin is empty and interactive (reads wait for content
+/// to be added), otherwise it holds exactly `input` and reads past the end see EOF.
+fn attach_stdio(emu: &mut csx64::exec::Emulator, input: Option<Vec<u8>>) -> (SharedFile, SharedFile, SharedFile) {
+    let interactive = input.is_none();
+    let stdin = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: std::io::Cursor::new(input.unwrap_or_default()), readable: true, writable: false, seekable: false, appendonly: false, interactive }));
+    let stdout = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: Default::default(), readable: false, writable: true, seekable: false, appendonly: true, interactive: false }));
+    let stderr = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: Default::default(), readable: false, writable: true, seekable: false, appendonly: true, interactive: false }));
+    emu.files.handles[0] = Some(stdin.clone());
+    emu.files.handles[1] = Some(stdout.clone());
+    emu.files.handles[2] = Some(stderr.clone());
+    (stdin, stdout, stderr)
+}
+
+/// Python source for the `State` and `StopKind` enums.
+/// Members are `str`-valued so that comparisons against the old magic strings keep working (with a deprecation warning).
+const ENUMS_SRC: &str = r#"
+import enum, warnings
+
+class _CompatEnum(str, enum.Enum):
+    def __eq__(self, other):
+        if isinstance(other, _CompatEnum):
+            return self is other # members of different enums are never equal, even with the same value
+        if type(other) is str:
+            warnings.warn(f'comparing {type(self).__name__} against a string is deprecated', DeprecationWarning, stacklevel = 2)
+        return str.__eq__(self, other)
+    def __ne__(self, other):
+        res = self.__eq__(other)
+        return res if res is NotImplemented else not res
+    __hash__ = str.__hash__
+    def __str__(self):
+        return self.value
+
+class State(_CompatEnum):
+    Uninitialized = 'Uninitialized'
+    Running = 'Running'
+    Terminated = 'Terminated'
+    Error = 'Error'
+
+class StopKind(_CompatEnum):
+    NotRunning = 'NotRunning'
+    MaxCycles = 'MaxCycles'
+    ForfeitTimeslot = 'ForfeitTimeslot'
+    Terminated = 'Terminated'
+    Error = 'Error'
+
+State.__module__ = StopKind.__module__ = 'csx64'
+"#;
+
+static STATE_ENUM: GILOnceCell<PyObject> = GILOnceCell::new();
+static STOP_KIND_ENUM: GILOnceCell<PyObject> = GILOnceCell::new();
+
+fn enum_member(py: Python, cls: &GILOnceCell<PyObject>, name: &str) -> PyResult<PyObject> {
+    cls.get(py).expect("csx64 enums not initialized").getattr(py, name)
+}
+
+fn state_name(state: &csx64::exec::State) -> &'static str {
+    match state {
+        csx64::exec::State::Uninitialized => "Uninitialized",
+        csx64::exec::State::Running => "Running",
+        csx64::exec::State::Terminated(_) => "Terminated",
+        csx64::exec::State::Error(_) => "Error",
+    }
+}
+fn stop_reason_name(reason: &csx64::exec::StopReason) -> &'static str {
+    match reason {
+        csx64::exec::StopReason::NotRunning => "NotRunning",
+        csx64::exec::StopReason::MaxCycles => "MaxCycles",
+        csx64::exec::StopReason::ForfeitTimeslot => "ForfeitTimeslot",
+        csx64::exec::StopReason::Terminated(_) => "Terminated",
+        csx64::exec::StopReason::Error(_) => "Error",
+    }
+}
+
+/// The reason `Emulator.execute_cycles` (or `Emulator.run_async`) returned.
+/// For backwards compatibility this unpacks like the old `(cycles, kind)` tuple.
+#[pyclass]
+struct StopReason {
+    kind: &'static str,
+    #[pyo3(get)] exit_code: Option<i32>,
+    #[pyo3(get)] error: Option<ExecErrorInfo>,
+    #[pyo3(get)] rip: u64,
+    #[pyo3(get)] cycles: u64,
+}
+impl StopReason {
+    fn new(emu: &Emulator, cycles: u64, reason: csx64::exec::StopReason) -> Self {
+        let kind = stop_reason_name(&reason);
+        let (exit_code, error) = match reason {
+            csx64::exec::StopReason::Terminated(code) => (Some(code), None),
+            csx64::exec::StopReason::Error(err) => (None, Some(ExecErrorInfo::new(emu, &err))),
+            _ => (None, None),
+        };
+        StopReason { kind, exit_code, error, rip: emu.0.instruction_pointer, cycles }
+    }
+    /// Converts an `Error` stop into the matching `ExecutionError` if `raise_on_error` is set.
+    fn check(self, py: Python, raise_on_error: bool) -> PyResult<Self> {
+        match &self.error {
+            Some(err) if raise_on_error => Err(err.to_exception(py)),
+            _ => Ok(self),
+        }
+    }
+}
+
+#[pymethods]
+impl StopReason {
+    #[getter] fn kind(&self, py: Python) -> PyResult<PyObject> { enum_member(py, &STOP_KIND_ENUM, self.kind) }
+}
+
+#[pyproto]
+impl PyObjectProtocol for StopReason {
+    fn __repr__(&self) -> String {
+        format!("StopReason(kind={:?}, exit_code={:?}, error={:?}, rip={:#x}, cycles={})", self.kind, self.exit_code, self.error.as_ref().map(|e| &e.kind), self.rip, self.cycles)
+    }
+}
+#[pyproto]
+impl PySequenceProtocol for StopReason {
+    fn __len__(&self) -> usize { 2 }
+    fn __getitem__(&self, idx: isize) -> PyResult<PyObject> {
+        Python::with_gil(|py| match idx {
+            0 | -2 => Ok(self.cycles.into_py(py)),
+            1 | -1 => enum_member(py, &STOP_KIND_ENUM, self.kind),
+            _ => Err(PyIndexError::new_err("StopReason index out of range")),
+        })
+    }
+}
+
+/// Awaitable returned by `Emulator.run_async`.
+/// Each step executes at most one slice of cycles and then yields to the event loop.
+#[pyclass]
+struct EmulatorTask {
+    emu: Py<Emulator>,
+    remaining: u64,
+    slice: u64,
+    cycles: u64,
+    raise_on_error: bool,
+    /// Set once the result has been returned - like a finished coroutine, further steps just stop.
+    done: bool,
+}
+
+#[pyproto]
+impl PyAsyncProtocol for EmulatorTask {
+    fn __await__(slf: PyRef<Self>) -> PyRef<Self> { slf }
+}
+#[pyproto]
+impl PyIterProtocol for EmulatorTask {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> { slf }
+    fn __next__(mut slf: PyRefMut<Self>) -> PyResult<IterNextOutput<Option<()>, StopReason>> {
+        let py = slf.py();
+        if slf.done {
+            return Err(PyStopIteration::new_err(()));
+        }
+        let emu = slf.emu.clone_ref(py);
+        let mut emu = emu.try_borrow_mut(py)?;
+
+        let (cycles, reason) = emu.0.execute_cycles(slf.slice.min(slf.remaining));
+        slf.cycles += cycles;
+        slf.remaining -= cycles;
+
+        Ok(match reason {
+            csx64::exec::StopReason::MaxCycles | csx64::exec::StopReason::ForfeitTimeslot if slf.remaining != 0 => IterNextOutput::Yield(None),
+            reason => {
+                slf.done = true;
+                IterNextOutput::Return(StopReason::new(&emu, slf.cycles, reason).check(py, slf.raise_on_error)?)
+            }
+        })
+    }
+}
+
 #[pymethods]
 impl Emulator {
     #[new]
     fn new() -> Self {
-        Emulator(csx64::exec::Emulator::new())
+        Emulator(csx64::exec::Emulator::new(), Default::default())
     }
     #[args(exe, "*", max_memory, stack_size, max_files, command_line_args = "vec![]")]
     fn init(&mut self, exe: Py<Executable>, max_memory: Option<usize>, stack_size: Option<usize>, max_files: Option<usize>, command_line_args: Vec<String>) {
@@ -34,31 +710,30 @@ impl Emulator {
             max_files,
             command_line_args,
         };
-        Python::with_gil(|py| self.0.init(&exe.borrow(py).0, &args));
-    }
-    #[args(cycles = "u64::MAX")]
-    fn execute_cycles(&mut self, cycles: u64) -> (u64, &'static str) {
-        let res = self.0.execute_cycles(cycles);
-        let reason = match res.1 {
-            csx64::exec::StopReason::NotRunning => "NotRunning",
-            csx64::exec::StopReason::MaxCycles => "MaxCycles",
-            csx64::exec::StopReason::ForfeitTimeslot => "ForfeitTimeslot",
-            csx64::exec::StopReason::Terminated(_) => "Terminated",
-            csx64::exec::StopReason::Error(_) => "Error",
-        };
-        (res.0, reason)
+        Python::with_gil(|py| {
+            let exe = exe.borrow(py);
+            self.0.init(&exe.0, &args);
+            self.1 = exe.1.clone();
+        });
     }
-    fn get_state(&self) -> &'static str {
-        match self.0.get_state() {
-            csx64::exec::State::Uninitialized => "Uninitialized",
-            csx64::exec::State::Running => "Running",
-            csx64::exec::State::Terminated(_) => "Terminated",
-            csx64::exec::State::Error(_) => "Error",
+    #[args(cycles = "u64::MAX", "*", raise_on_error = "false")]
+    fn execute_cycles(&mut self, py: Python, cycles: u64, raise_on_error: bool) -> PyResult<StopReason> {
+        let (cycles, reason) = self.0.execute_cycles(cycles);
+        StopReason::new(self, cycles, reason).check(py, raise_on_error)
+    }
+    #[args(cycles = "u64::MAX", "*", slice = "100_000", raise_on_error = "false")]
+    fn run_async(slf: PyRef<Self>, cycles: u64, slice: u64, raise_on_error: bool) -> PyResult<EmulatorTask> {
+        if slice == 0 {
+            return Err(PyValueError::new_err("slice must be positive"));
         }
+        Ok(EmulatorTask { emu: slf.into(), remaining: cycles, slice, cycles: 0, raise_on_error, done: false })
+    }
+    fn get_state(&self, py: Python) -> PyResult<PyObject> {
+        enum_member(py, &STATE_ENUM, state_name(&self.0.get_state()))
     }
-    fn get_error(&self) -> Option<String> {
+    fn get_error(&self) -> Option<ExecErrorInfo> {
         match self.0.get_state() {
-            csx64::exec::State::Error(err) => Some(format!("{:?}", err)),
+            csx64::exec::State::Error(err) => Some(ExecErrorInfo::new(self, &err)),
             _ => None,
         }
     }
@@ -70,15 +745,13 @@ impl Emulator {
     }
 
     fn setup_stdio(&mut self) -> (MemoryFile, MemoryFile, MemoryFile) {
-        let stdin = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: Default::default(), readable: true, writable: false, seekable: false, appendonly: false, interactive: true }));
-        let stdout = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: Default::default(), readable: false, writable: true, seekable: false, appendonly: true, interactive: false }));
-        let stderr = Arc::new(Mutex::new(csx64::exec::fs::MemoryFile { content: Default::default(), readable: false, writable: true, seekable: false, appendonly: true, interactive: false }));
-        self.0.files.handles[0] = Some(stdin.clone());
-        self.0.files.handles[1] = Some(stdout.clone());
-        self.0.files.handles[2] = Some(stderr.clone());
+        let (stdin, stdout, stderr) = attach_stdio(&mut self.0, None);
         (MemoryFile(stdin), MemoryFile(stdout), MemoryFile(stderr))
     }
 
+    #[getter] fn get_rip(&self) -> u64 { self.0.instruction_pointer }
+    #[setter] fn set_rip(&mut self, value: u64) { self.0.instruction_pointer = value }
+
     #[getter] fn get_flags(&self) -> u64 { self.0.flags.0 }
     #[setter] fn set_flags(&mut self, value: u64) { self.0.flags.0 = value }
 
@@ -430,41 +1103,700 @@ impl Emulator {
     #[setter] fn set_dhi(&mut self, value: i8) { self.0.cpu.set_dh(value as u8) }
 }
 
+/// A value which can be predefined as an assembler symbol.
+#[derive(Clone, Copy)]
+enum PredefineValue {
+    Int(i64),
+    /// Integers above `i64::MAX` (e.g. 64-bit masks).
+    UInt(u64),
+    Float(f64),
+}
+impl<'a> FromPyObject<'a> for PredefineValue {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        // bool is a subclass of int, but True/False as a symbol value is almost certainly a mistake
+        if ob.is_instance::<PyBool>()? {
+            return Err(PyTypeError::new_err("predefine values must be int or float, not bool"));
+        }
+        if ob.is_instance::<PyLong>()? {
+            if let Ok(v) = ob.extract::<i64>() {
+                return Ok(PredefineValue::Int(v));
+            }
+            return ob.extract::<u64>().map(PredefineValue::UInt).map_err(|_| PyValueError::new_err(format!("predefine value {} does not fit in 64 bits", ob)));
+        }
+        if ob.is_instance::<PyFloat>()? {
+            return Ok(PredefineValue::Float(ob.extract()?));
+        }
+        Err(PyTypeError::new_err(format!("predefine values must be int or float, not {}", ob.get_type().name()?)))
+    }
+}
+
+fn is_valid_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Assembly source given as `str`, `bytes`, or any readable (text or binary) stream.
+struct AsmSource(Vec<u8>);
+impl<'a> FromPyObject<'a> for AsmSource {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        if let Ok(s) = ob.downcast::<PyString>() {
+            return Ok(AsmSource(s.to_str()?.as_bytes().to_vec()));
+        }
+        if let Ok(b) = ob.downcast::<PyBytes>() {
+            return Ok(AsmSource(b.as_bytes().to_vec()));
+        }
+        if ob.hasattr("read")? {
+            return ob.call_method0("read")?.extract();
+        }
+        Err(PyTypeError::new_err(format!("expected str, bytes, or a readable stream, got {}", ob.get_type().name()?)))
+    }
+}
+
+/// Data for a program's stdin given as `bytes`, `bytearray`, or a readable binary stream.
+struct StdinData(Vec<u8>);
+impl<'a> FromPyObject<'a> for StdinData {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        if let Ok(b) = ob.downcast::<PyBytes>() {
+            return Ok(StdinData(b.as_bytes().to_vec()));
+        }
+        if let Ok(b) = ob.downcast::<PyByteArray>() {
+            return Ok(StdinData(b.to_vec()));
+        }
+        if ob.hasattr("read")? {
+            let data = ob.call_method0("read")?;
+            return match data.downcast::<PyBytes>() {
+                Ok(b) => Ok(StdinData(b.as_bytes().to_vec())),
+                Err(_) => Err(PyTypeError::new_err(format!("stdin stream must be opened in binary mode (read() returned {})", data.get_type().name()?))),
+            };
+        }
+        Err(PyTypeError::new_err(format!("stdin must be bytes, bytearray, or a readable binary stream, got {}", ob.get_type().name()?)))
+    }
+}
+
+/// A value for a preprocessor `%define`.
+#[derive(FromPyObject)]
+enum DefineValue {
+    Int(i64),
+    Str(String),
+}
+
+/// Assembler keyword arguments as given from python (see `AsmOptions` for the validated form).
+#[derive(Default)]
+struct AsmArgs {
+    predefines: Option<HashMap<String, PredefineValue>>,
+    warnings_as_errors: bool,
+    include_path: Option<Vec<PathBuf>>,
+    include_files: Option<HashMap<String, String>>,
+    preprocess: bool,
+    defines: Option<HashMap<String, DefineValue>>,
+    listing: bool,
+    debug: bool,
+}
+impl AsmArgs {
+    /// Takes the arguments from a `**kwargs` dict (for functions which forward them).
+    fn from_kwargs(kwargs: Option<&PyDict>) -> PyResult<Self> {
+        let mut res = AsmArgs::default();
+        for (key, value) in kwargs.into_iter().flatten() {
+            match key.extract::<&str>()? {
+                "predefines" => res.predefines = value.extract()?,
+                "warnings_as_errors" => res.warnings_as_errors = value.extract()?,
+                "include_path" => res.include_path = value.extract()?,
+                "include_files" => res.include_files = value.extract()?,
+                "preprocess" => res.preprocess = value.extract()?,
+                "defines" => res.defines = value.extract()?,
+                "listing" => res.listing = value.extract()?,
+                "debug" => res.debug = value.extract()?,
+                key => return Err(PyTypeError::new_err(format!("unexpected keyword argument '{}'", key))),
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Validated assembler options shared by all of the assemble functions.
+struct AsmOptions {
+    predefines: Vec<(String, PredefineValue)>,
+    warnings_as_errors: bool,
+    includes: preprocess::IncludeResolver,
+    defines: Option<HashMap<String, String>>,
+    listing: bool,
+}
+impl AsmOptions {
+    fn new(args: AsmArgs) -> PyResult<Self> {
+        let AsmArgs { predefines, warnings_as_errors, include_path, include_files, preprocess, defines, listing, debug } = args;
+        let listing = listing || debug;
+        let predefines: Vec<_> = predefines.unwrap_or_default().into_iter().collect();
+        for (name, value) in predefines.iter() {
+            if !is_valid_symbol(name) {
+                return Err(PyValueError::new_err(format!("predefine '{}' is not a valid symbol name", name)));
+            }
+            if let PredefineValue::Float(v) = value {
+                if !v.is_finite() {
+                    return Err(PyValueError::new_err(format!("predefine '{}' has non-finite value {}", name, v)));
+                }
+            }
+        }
+        let includes = preprocess::IncludeResolver {
+            virtual_files: include_files.unwrap_or_default(),
+            search_path: include_path,
+        };
+        let defines = match (preprocess, defines) {
+            (false, Some(_)) => return Err(PyValueError::new_err("defines requires preprocess=True")),
+            (false, None) => None,
+            (true, defines) => {
+                let mut res = HashMap::new();
+                for (name, value) in defines.unwrap_or_default() {
+                    if !is_valid_symbol(&name) {
+                        return Err(PyValueError::new_err(format!("define '{}' is not a valid symbol name", name)));
+                    }
+                    res.insert(name, match value { DefineValue::Int(v) => v.to_string(), DefineValue::Str(v) => v });
+                }
+                Some(res)
+            }
+        };
+        Ok(AsmOptions { predefines, warnings_as_errors, includes, defines, listing })
+    }
+    fn from_kwargs(kwargs: Option<&PyDict>) -> PyResult<Self> {
+        AsmOptions::new(AsmArgs::from_kwargs(kwargs)?)
+    }
+    /// Runs include expansion and (if enabled) the macro preprocessor.
+    fn preprocess(&self, asm_name: &str, src: &[u8]) -> Result<preprocess::Expanded, preprocess::Error> {
+        let src = preprocess::decode(asm_name, src)?;
+        match &self.defines {
+            Some(defines) => preprocess::Preprocessor::new(defines.clone(), &self.includes).run(asm_name, src),
+            None => preprocess::expand_includes(asm_name, src, &self.includes),
+        }
+    }
+    /// Writes the options which affect assembly of already-preprocessed source to a cache key.
+    fn write_key(&self, w: &mut serial::Writer) {
+        let mut predefines: Vec<_> = self.predefines.iter().collect();
+        predefines.sort_by(|a, b| a.0.cmp(&b.0));
+        w.seq(&predefines, |w, (name, value)| {
+            w.str(name);
+            match *value {
+                PredefineValue::Int(v) => { w.u8(0); w.u64(v as u64) }
+                PredefineValue::UInt(v) => { w.u8(2); w.u64(v) }
+                PredefineValue::Float(v) => { w.u8(1); w.u64(v.to_bits()) }
+            }
+        });
+        w.u8(self.listing as u8);
+    }
+    /// Builds the assembler arguments (these are consumed by each call to `csx64::asm::assemble`).
+    fn asm_args(&self) -> csx64::asm::Predefines {
+        let mut res = HashMap::new();
+        for (name, value) in self.predefines.iter() {
+            let value = match *value {
+                PredefineValue::Int(v) => csx64::asm::expr::Expr::from(v),
+                PredefineValue::UInt(v) => csx64::asm::expr::Expr::from(v),
+                PredefineValue::Float(v) => csx64::asm::expr::Expr::from(v),
+            };
+            res.insert(name.clone(), value);
+        }
+        csx64::asm::Predefines::from(res)
+    }
+}
+
+enum AsmFailure {
+    Preprocess(preprocess::Error),
+    Assemble(csx64::asm::AsmError, preprocess::Expanded),
+    /// The source assembled but its listing couldn't be built.
+    Listing(csx64::asm::AsmError, Arc<str>),
+}
+type AsmResult = Result<ObjectFile, AsmFailure>;
+
+/// Assembles a single source without touching any python objects, so it can run with the GIL released.
+fn assemble_raw(asm_name: &str, src: &[u8], opts: &AsmOptions) -> AsmResult {
+    assemble_expanded(asm_name, opts.preprocess(asm_name, src).map_err(AsmFailure::Preprocess)?, opts)
+}
+fn assemble_expanded(asm_name: &str, src: preprocess::Expanded, opts: &AsmOptions) -> AsmResult {
+    let obj = match csx64::asm::assemble(asm_name, &mut src.text.as_bytes(), opts.asm_args()) {
+        Ok(v) => v,
+        Err(e) => return Err(AsmFailure::Assemble(e, src)),
+    };
+    let warnings = lint::lint(&src.text).into_iter().map(|d| {
+        let origin = src.origin(d.line);
+        AsmWarning { code: d.code, message: d.message, file: origin.file.to_string(), line: origin.line, include_chain: include_chain(&origin) }
+    }).collect();
+    let listing = if opts.listing {
+        match listing::build(asm_name, &src, opts.asm_args()) {
+            Ok(v) => Some(Arc::new(v)),
+            Err(e) => return Err(AsmFailure::Listing(e, asm_name.into())),
+        }
+    } else {
+        None
+    };
+    Ok(ObjectFile::new(Arc::new(obj), ObjectMeta { warnings, listing }))
+}
+fn finish_assemble(py: Python, res: AsmResult, opts: &AsmOptions) -> PyResult<ObjectFile> {
+    let obj = match res {
+        Ok(v) => v,
+        Err(AsmFailure::Preprocess(e)) => return Err(preprocess_error(py, e)),
+        Err(AsmFailure::Assemble(e, src)) => return Err(assemble_error(py, e, &src)),
+        Err(AsmFailure::Listing(e, file)) => return Err(listing_error(py, e, &file)),
+    };
+    if opts.warnings_as_errors {
+        if let Some(w) = obj.1.warnings.first() {
+            return Err(assemble_error_attrs(py, AssembleError::new_err(w.to_string()), &w.file, Some(w.line), None, w.code, &w.message, &w.include_chain));
+        }
+    }
+    Ok(obj)
+}
+
+#[pyfunction]
+#[args(asm_name, src, "*", predefines = "None", warnings_as_errors = "false", include_path = "None", include_files = "None", preprocess = "false", defines = "None", listing = "false", debug = "false")]
+#[allow(clippy::too_many_arguments)]
+fn assemble(py: Python, asm_name: &str, src: AsmSource, predefines: Option<HashMap<String, PredefineValue>>, warnings_as_errors: bool, include_path: Option<Vec<PathBuf>>, include_files: Option<HashMap<String, String>>, preprocess: bool, defines: Option<HashMap<String, DefineValue>>, listing: bool, debug: bool) -> PyResult<ObjectFile> {
+    let opts = AsmOptions::new(AsmArgs { predefines, warnings_as_errors, include_path, include_files, preprocess, defines, listing, debug })?;
+    finish_assemble(py, assemble_raw(asm_name, &src.0, &opts), &opts)
+}
+#[pyfunction]
+#[args(path, "*", predefines = "None", warnings_as_errors = "false", include_path = "None", include_files = "None", preprocess = "false", defines = "None", listing = "false", debug = "false")]
+#[allow(clippy::too_many_arguments)]
+fn assemble_file(py: Python, path: PathBuf, predefines: Option<HashMap<String, PredefineValue>>, warnings_as_errors: bool, include_path: Option<Vec<PathBuf>>, include_files: Option<HashMap<String, String>>, preprocess: bool, defines: Option<HashMap<String, DefineValue>>, listing: bool, debug: bool) -> PyResult<ObjectFile> {
+    let opts = AsmOptions::new(AsmArgs { predefines, warnings_as_errors, include_path, include_files, preprocess, defines, listing, debug })?;
+    let src = std::fs::read(&path)?;
+    finish_assemble(py, assemble_raw(&path.display().to_string(), &src, &opts), &opts)
+}
+#[pyfunction]
+#[args(sources, "*", predefines = "None", warnings_as_errors = "false", include_path = "None", include_files = "None", preprocess = "false", defines = "None", listing = "false", debug = "false")]
+#[allow(clippy::too_many_arguments)]
+fn assemble_many(py: Python, sources: Vec<(String, AsmSource)>, predefines: Option<HashMap<String, PredefineValue>>, warnings_as_errors: bool, include_path: Option<Vec<PathBuf>>, include_files: Option<HashMap<String, String>>, preprocess: bool, defines: Option<HashMap<String, DefineValue>>, listing: bool, debug: bool) -> PyResult<Vec<(String, ObjectFile)>> {
+    let opts = AsmOptions::new(AsmArgs { predefines, warnings_as_errors, include_path, include_files, preprocess, defines, listing, debug })?;
+    assemble_many_raw(py, sources, &opts)
+}
+/// Assembles the sources in parallel (with the GIL released), shared by `assemble_many` and `run`.
+fn assemble_many_raw(py: Python, sources: Vec<(String, AsmSource)>, opts: &AsmOptions) -> PyResult<Vec<(String, ObjectFile)>> {
+    let results: std::thread::Result<Vec<AsmResult>> = py.allow_threads(|| {
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = sources.len().div_ceil(threads).max(1);
+        std::thread::scope(|s| {
+            let handles: Vec<_> = sources.chunks(chunk_size).map(|chunk| s.spawn(move || {
+                chunk.iter().map(|(name, src)| assemble_raw(name, &src.0, opts)).collect::<Vec<_>>()
+            })).collect();
+            // join every worker before reporting a panic so none are left running
+            let joined: Vec<_> = handles.into_iter().map(|h| h.join()).collect();
+            joined.into_iter().try_fold(vec![], |mut res, chunk| { res.extend(chunk?); Ok(res) })
+        })
+    });
+    // a panic must not unwind across the FFI boundary - raise it like pyo3 does for panics on the calling thread
+    let results = results.map_err(|e| {
+        let msg = e.downcast_ref::<&str>().map(|s| s.to_string()).or_else(|| e.downcast_ref::<String>().cloned()).unwrap_or_else(|| "assembler thread panicked".into());
+        pyo3::panic::PanicException::new_err(msg)
+    })?;
+    sources.iter().zip(results).map(|((name, _), res)| Ok((name.clone(), finish_assemble(py, res, opts)?))).collect()
+}
+#[pyfunction]
+#[args(src, "*", asm_name = "\"<source>\"", defines = "None", include_path = "None", include_files = "None")]
+fn preprocess(py: Python, src: AsmSource, asm_name: &str, defines: Option<HashMap<String, DefineValue>>, include_path: Option<Vec<PathBuf>>, include_files: Option<HashMap<String, String>>) -> PyResult<String> {
+    let opts = AsmOptions::new(AsmArgs { include_path, include_files, preprocess: true, defines, ..Default::default() })?;
+    match opts.preprocess(asm_name, &src.0) {
+        Ok(v) => Ok(v.text),
+        Err(e) => Err(preprocess_error(py, e)),
+    }
+}
+/// Links already-extracted objects without touching any python objects, so it can run with the GIL released.
+fn link_raw(objs: &[(String, Arc<csx64::asm::ObjectFile>, Option<Arc<Vec<listing::Entry>>>)], entry_point: Option<(&str, &str)>, map: bool) -> Result<(Executable, Option<LinkMap>), csx64::asm::LinkError> {
+    let inputs: Vec<(&str, &csx64::asm::ObjectFile)> = objs.iter().map(|(name, obj, _)| (name.as_str(), &**obj)).collect();
+    let (exe, layout) = inspect::link(&inputs, entry_point)?;
+    let linked: Vec<_> = objs.iter().map(|(name, obj, lines)| debuginfo::LinkedObject {
+        name,
+        obj,
+        lines: lines.as_deref().map(Vec::as_slice),
+    }).collect();
+    let info = debuginfo::DebugInfo::build(&linked, &layout.objects);
+    let map = map.then(|| LinkMap(linkmap::LinkMap::build(&exe, &linked, &layout.objects, &info)));
+    Ok((Executable(exe, Arc::new(info), layout.entry_point), map))
+}
+fn link_objects(py: Python, objs: &[(String, Py<ObjectFile>)], entry_point: Option<(&str, &str)>, map: bool) -> PyResult<(Executable, Option<LinkMap>)> {
+    // only take shared handles while holding the GIL - linking happens after releasing it
+    let handles: Vec<_> = objs.iter().map(|(name, obj)| {
+        let obj = obj.borrow(py);
+        (name.clone(), obj.0.clone(), obj.1.listing.clone())
+    }).collect();
+    match py.allow_threads(|| link_raw(&handles, entry_point, map)) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(link_error(py, objs, entry_point, e)),
+    }
+}
+/// Appends the archive members needed to resolve the externs (and entry point) of the objects being linked.
+fn add_archive_members(py: Python, objs: &mut Vec<(String, Py<ObjectFile>)>, entry_point: Option<(&str, &str)>, archives: &[Py<Archive>]) -> PyResult<()> {
+    let archives: Vec<PyRef<Archive>> = archives.iter().map(|a| a.borrow(py)).collect();
+    let members: Vec<&(String, ObjectFile)> = archives.iter().flat_map(|a| a.0.iter()).collect();
+    let selected = {
+        let refs: Vec<PyRef<ObjectFile>> = objs.iter().map(|(_, obj)| obj.borrow(py)).collect();
+        let inputs: Vec<_> = objs.iter().zip(refs.iter()).map(|((name, _), obj)| (name.as_str(), &*obj.0)).collect();
+        let candidates: Vec<_> = members.iter().map(|(name, obj)| (name.as_str(), &*obj.0)).collect();
+        archive::select(&inputs, &candidates, entry_point)
+    };
+    for i in selected {
+        objs.push((members[i].0.clone(), Py::new(py, members[i].1.clone())?));
+    }
+    Ok(())
+}
+
+/// Links the objects into an executable. With `map=True` this returns `(executable, map)` where `map` is a `LinkMap`.
+/// Members of `archives` are only linked if they define a symbol which would otherwise be unresolved.
 #[pyfunction]
-fn assemble(asm_name: &str, src: &str) -> PyResult<ObjectFile> {
-    match csx64::asm::assemble(asm_name, &mut src.as_bytes(), Default::default()) {
-        Ok(v) => Ok(ObjectFile(v)),
-        Err(e) => Err(PyRuntimeError::new_err(format!("{}", e))),
+#[args(objs, entry_point = "None", "*", map = "false", archives = "None")]
+fn link(py: Python, mut objs: Vec<(String, Py<ObjectFile>)>, entry_point: Option<(&str, &str)>, map: bool, archives: Option<Vec<Py<Archive>>>) -> PyResult<PyObject> {
+    add_archive_members(py, &mut objs, entry_point, archives.as_deref().unwrap_or_default())?;
+    Ok(match link_objects(py, &objs, entry_point, map)? {
+        (exe, Some(map)) => (exe, map).into_py(py),
+        (exe, None) => exe.into_py(py),
+    })
+}
+
+/// Report of how the linker laid out an executable (`str()` gives a printable map file).
+#[pyclass]
+struct LinkMap(linkmap::LinkMap);
+
+#[pymethods]
+impl LinkMap {
+    /// Gets the executable's segments as `(segment, address, size)`.
+    #[getter] fn segments(&self) -> Vec<(&'static str, u64, usize)> {
+        self.0.segments.iter().map(|&(seg, address, size)| (seg.name(), address, size)).collect()
+    }
+    /// Gets each object's part of each segment as `(object, segment, address, size)`.
+    #[getter] fn contributions(&self) -> Vec<(&str, &'static str, u64, usize)> {
+        self.0.contributions.iter().map(|c| (&*c.object, c.segment.name(), c.address, c.size)).collect()
+    }
+    /// Gets the global symbols as `(name, address, object)`.
+    #[getter] fn symbols(&self) -> Vec<(&str, u64, &str)> {
+        self.0.symbols.iter().map(|(name, address, object)| (name.as_str(), *address, &**object)).collect()
+    }
+    /// Gets the padding between contributions as `(segment, address, size)`.
+    #[getter] fn gaps(&self) -> Vec<(&'static str, u64, usize)> {
+        self.0.gaps.iter().map(|g| (g.segment.name(), g.address, g.size)).collect()
+    }
+}
+#[pyproto]
+impl PyObjectProtocol for LinkMap {
+    fn __str__(&self) -> String { self.0.to_string() }
+}
+
+/// A static library of named objects (see the `archives` argument of `link`).
+#[derive(Clone, Default)]
+#[pyclass]
+struct Archive(Vec<(String, ObjectFile)>);
+
+impl Archive {
+    fn encode(&self) -> Vec<u8> {
+        serial::write_with(serial::Kind::Archive, |w| w.seq(&self.0, |w, (name, obj)| { w.str(name); w.bytes(&obj.encode()) }))
+    }
+    fn decode(data: &[u8]) -> Result<Self, serial::Error> {
+        let mut r = serial::read_header(serial::Kind::Archive, data)?;
+        let members = r.seq(|r| Ok((r.str()?, ObjectFile::decode(r.bytes()?)?)))?;
+        r.finish()?;
+        Ok(Archive(members))
+    }
+}
+
+#[pymethods]
+impl Archive {
+    #[new]
+    #[args(members = "None")]
+    fn new(members: Option<Vec<(String, ObjectFile)>>) -> Self {
+        Archive(members.unwrap_or_default())
+    }
+    fn add(&mut self, name: String, obj: ObjectFile) {
+        self.0.push((name, obj));
     }
+    #[getter] fn members(&self) -> Vec<(String, ObjectFile)> { self.0.clone() }
+
+    /// Serializes the archive (along with the warnings and listings of its members) in a versioned binary format.
+    fn to_bytes(&self, py: Python) -> PyObject {
+        PyBytes::new(py, &self.encode()).into()
+    }
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        Archive::decode(data).map_err(|e| FormatError::new_err(e.to_string()))
+    }
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        Ok(std::fs::write(path, self.encode())?)
+    }
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        let data = std::fs::read(&path)?;
+        Archive::decode(&data).map_err(|e| FormatError::new_err(format!("{}: {}", path.display(), e)))
+    }
+}
+#[pyproto]
+impl PySequenceProtocol for Archive {
+    fn __len__(&self) -> usize { self.0.len() }
 }
+
+/// The assembled stdlib, built on first use and shared by every object `stdlib` returns.
+/// Clones share their cached digests too, so linking the stdlib through a `BuildCache` doesn't rehash it every time.
+static STDLIB: GILOnceCell<Vec<(String, ObjectFile)>> = GILOnceCell::new();
+
+fn stdlib_objects(py: Python<'_>) -> &[(String, ObjectFile)] {
+    STDLIB.get_or_init(py, || csx64::asm::stdlib().into_iter().map(|(name, obj)| (name, ObjectFile::new(Arc::new(obj), Default::default()))).collect())
+}
+fn stdlib_views(objs: &[(String, ObjectFile)]) -> Vec<(&str, &csx64::asm::ObjectFile)> {
+    objs.iter().map(|(name, obj)| (name.as_str(), &*obj.0)).collect()
+}
+
+/// Gets the stdlib objects to link. `include` and `exclude` take module names or the names of functions they export:
+/// included modules bring along the modules they depend on, and excluded modules take the modules which depend on them.
 #[pyfunction]
-fn link(objs: Vec<(String, Py<ObjectFile>)>, entry_point: Option<(&str, &str)>) -> PyResult<Executable> {
-    let objs = Python::with_gil(|py| -> PyResult<Vec<(String, csx64::asm::ObjectFile)>> {
-        let mut robjs = Vec::with_capacity(objs.len());
-        for obj in objs.into_iter() {
-            robjs.push((obj.0, obj.1.extract::<ObjectFile>(py)?.0));
+#[args("*", include = "None", exclude = "None")]
+fn stdlib(py: Python, include: Option<Vec<String>>, exclude: Option<Vec<String>>) -> PyResult<Vec<(String, ObjectFile)>> {
+    let objs = stdlib_objects(py);
+    let selected = stdlib::select(&stdlib_views(objs), include.as_deref(), exclude.as_deref().unwrap_or_default()).map_err(PyValueError::new_err)?;
+    Ok(selected.into_iter().map(|i| objs[i].clone()).collect())
+}
+
+/// Description of a stdlib module.
+#[pyclass]
+struct StdlibModule {
+    #[pyo3(get)] name: String,
+    /// Global symbols (functions and data) the module defines.
+    #[pyo3(get)] exports: Vec<String>,
+    /// Other modules which are linked along with this one.
+    #[pyo3(get)] dependencies: Vec<String>,
+    /// Total size of all its segments.
+    #[pyo3(get)] size: usize,
+}
+#[pyproto]
+impl PyObjectProtocol for StdlibModule {
+    fn __repr__(&self) -> String {
+        format!("StdlibModule(name={:?}, exports={:?}, dependencies={:?}, size={})", self.name, self.exports, self.dependencies, self.size)
+    }
+}
+
+#[pyfunction]
+fn stdlib_modules(py: Python) -> Vec<StdlibModule> {
+    let objs = stdlib_objects(py);
+    objs.iter().zip(stdlib::dependencies(&stdlib_views(objs))).map(|((name, obj), deps)| {
+        let mut exports: Vec<String> = inspect::global_symbols(&obj.0).map(str::to_owned).collect();
+        exports.sort_unstable();
+        StdlibModule {
+            name: name.clone(),
+            exports,
+            dependencies: deps.into_iter().map(|i| objs[i].0.clone()).collect(),
+            size: inspect::Segment::ALL.iter().map(|&seg| inspect::segment_len(&obj.0, seg)).sum(),
+        }
+    }).collect()
+}
+
+/// On-disk cache of assembled objects and linked executables.
+/// Assembly is keyed on the preprocessed source (so changes to included files are picked up), link on the digest and listing
+/// of each input object (so debug info is keyed too). Failures are never cached, and neither are results the cache directory
+/// can't take - writing the cache is best-effort.
+#[pyclass]
+struct BuildCache {
+    dir: PathBuf,
+    #[pyo3(get)] hits: usize,
+    #[pyo3(get)] misses: usize,
+}
+impl BuildCache {
+    fn path(&self, key: &str, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, ext))
+    }
+    fn get<T>(&mut self, key: &str, ext: &str, decode: impl FnOnce(&[u8]) -> Result<T, serial::Error>) -> Option<T> {
+        // unreadable or corrupt entries are treated as misses and overwritten
+        let res = std::fs::read(self.path(key, ext)).ok().and_then(|data| decode(&data).ok());
+        match res {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        res
+    }
+    fn put(&self, key: &str, ext: &str, data: &[u8]) {
+        // write then rename so other processes sharing the directory never see a partial entry
+        let tmp = self.dir.join(format!("{}.{}.{}.tmp", key, ext, std::process::id()));
+        if std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, self.path(key, ext))).is_err() {
+            let _ = std::fs::remove_file(&tmp);
         }
-        Ok(robjs)
-    })?;
-    match csx64::asm::link(objs, entry_point) {
-        Ok(v) => Ok(Executable(v)),
-        Err(e) => Err(PyRuntimeError::new_err(format!("{}", e))),
     }
 }
+#[pymethods]
+impl BuildCache {
+    #[new]
+    fn new(directory: PathBuf) -> PyResult<Self> {
+        std::fs::create_dir_all(&directory)?;
+        Ok(BuildCache { dir: directory, hits: 0, misses: 0 })
+    }
+
+    /// Same as `csx64.assemble`, but reuses a previous result for identical input.
+    #[args(asm_name, src, "*", predefines = "None", warnings_as_errors = "false", include_path = "None", include_files = "None", preprocess = "false", defines = "None", listing = "false", debug = "false")]
+    #[allow(clippy::too_many_arguments)]
+    fn assemble(&mut self, py: Python, asm_name: &str, src: AsmSource, predefines: Option<HashMap<String, PredefineValue>>, warnings_as_errors: bool, include_path: Option<Vec<PathBuf>>, include_files: Option<HashMap<String, String>>, preprocess: bool, defines: Option<HashMap<String, DefineValue>>, listing: bool, debug: bool) -> PyResult<ObjectFile> {
+        let opts = AsmOptions::new(AsmArgs { predefines, warnings_as_errors, include_path, include_files, preprocess, defines, listing, debug })?;
+        let src = opts.preprocess(asm_name, &src.0).map_err(|e| preprocess_error(py, e))?;
+        let key = serial::key(|w| {
+            w.str(asm_name);
+            opts.write_key(w);
+            w.str(&src.text);
+            w.seq(&src.origins, |w, o| {
+                w.str(&o.file);
+                w.usize(o.line);
+                w.seq(o.chain.as_slice(), |w, (file, line)| { w.str(file); w.usize(*line) });
+            });
+        });
+
+        let res = match self.get(&key, "obj", ObjectFile::decode) {
+            Some(obj) => Ok(obj),
+            None => {
+                let res = assemble_expanded(asm_name, src, &opts);
+                if let Ok(obj) = &res {
+                    self.put(&key, "obj", &obj.encode());
+                }
+                res
+            }
+        };
+        finish_assemble(py, res, &opts)
+    }
+    /// Same as `csx64.link`, but reuses a previous result for identical input.
+    #[args(objs, entry_point = "None", "*", archives = "None")]
+    fn link(&mut self, py: Python, mut objs: Vec<(String, Py<ObjectFile>)>, entry_point: Option<(&str, &str)>, archives: Option<Vec<Py<Archive>>>) -> PyResult<Executable> {
+        add_archive_members(py, &mut objs, entry_point, archives.as_deref().unwrap_or_default())?;
+        let key = serial::key(|w| {
+            w.seq(&objs, |w, (name, obj)| {
+                let obj = obj.borrow(py);
+                w.str(name);
+                w.bytes(&obj.digest_raw());
+                w.opt(obj.1.listing.as_deref(), |w, lines| w.seq(lines, |w, l| l.write(w)));
+            });
+            w.opt(entry_point, |w, (source, target)| { w.str(source); w.str(target) });
+        });
+        if let Some(exe) = self.get(&key, "exe", Executable::decode) {
+            return Ok(exe);
+        }
+        let (exe, _) = link_objects(py, &objs, entry_point, false)?;
+        self.put(&key, "exe", &exe.encode());
+        Ok(exe)
+    }
+}
+
+/// Result of `csx64.run`, in the spirit of `subprocess.CompletedProcess`.
+#[pyclass]
+struct CompletedRun {
+    /// `None` unless the program terminated normally.
+    #[pyo3(get)] exit_code: Option<i32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    #[pyo3(get)] cycles: u64,
+    state: &'static str,
+    #[pyo3(get)] error: Option<ExecErrorInfo>,
+    /// Set if execution was cut off by `timeout` (running out of `max_cycles` just leaves the state as `Running`).
+    #[pyo3(get)] timed_out: bool,
+}
+#[pymethods]
+impl CompletedRun {
+    #[getter] fn stdout(&self, py: Python) -> PyObject { PyBytes::new(py, &self.stdout).into() }
+    #[getter] fn stderr(&self, py: Python) -> PyObject { PyBytes::new(py, &self.stderr).into() }
+    #[getter] fn state(&self, py: Python) -> PyResult<PyObject> { enum_member(py, &STATE_ENUM, self.state) }
+}
+#[pyproto]
+impl PyObjectProtocol for CompletedRun {
+    fn __repr__(&self) -> String {
+        format!("CompletedRun(state={:?}, exit_code={:?}, cycles={}, error={:?}, timed_out={})", self.state, self.exit_code, self.cycles, self.error.as_ref().map(|e| &e.kind), self.timed_out)
+    }
+}
+
+/// Assembles `sources` (a list of `(name, source)`), links them with the stdlib starting at `main`, and runs the result.
+/// Assembly and link failures are raised as usual; execution problems are reported in the result.
+/// `stdin` (bytes, bytearray, or a binary stream) is the program's entire input (empty if not given).
+/// Any other keyword arguments are passed on to the assembler.
 #[pyfunction]
-fn stdlib() -> Vec<(String, ObjectFile)> {
-    csx64::asm::stdlib().into_iter().map(|v| (v.0, ObjectFile(v.1))).collect()
+#[args(sources, "*", stdin = "None", args = "vec![]", max_cycles = "u64::MAX", timeout = "None", kwargs = "**")]
+fn run(py: Python, sources: Vec<(String, AsmSource)>, stdin: Option<StdinData>, args: Vec<String>, max_cycles: u64, timeout: Option<f64>, kwargs: Option<&PyDict>) -> PyResult<CompletedRun> {
+    let deadline = match timeout {
+        Some(t) => {
+            let t = std::time::Duration::try_from_secs_f64(t).map_err(|_| PyValueError::new_err("timeout must be a non-negative number of seconds"))?;
+            std::time::Instant::now().checked_add(t) // too far in the future to represent is the same as no timeout
+        }
+        None => None,
+    };
+
+    let opts = AsmOptions::from_kwargs(kwargs)?;
+    let mut objs = vec![];
+    for (name, obj) in stdlib(py, None, None)?.into_iter().chain(assemble_many_raw(py, sources, &opts)?) {
+        objs.push((name, Py::new(py, obj)?));
+    }
+    let (exe, _) = link_objects(py, &objs, Some(("start", "main")), false)?;
+
+    let mut emu = Emulator(csx64::exec::Emulator::new(), exe.1.clone());
+    emu.0.init(&exe.0, &csx64::exec::EmulatorArgs { max_memory: None, stack_size: None, max_files: None, command_line_args: args });
+    let (_, stdout, stderr) = attach_stdio(&mut emu.0, Some(stdin.map(|v| v.0).unwrap_or_default()));
+
+    // run in slices so the timeout gets checked
+    let (cycles, timed_out) = py.allow_threads(|| {
+        let mut cycles = 0;
+        loop {
+            let (n, reason) = emu.0.execute_cycles((max_cycles - cycles).min(100_000));
+            cycles += n;
+            match reason {
+                csx64::exec::StopReason::MaxCycles | csx64::exec::StopReason::ForfeitTimeslot if cycles < max_cycles => (),
+                _ => return (cycles, false),
+            }
+            if deadline.map(|d| std::time::Instant::now() >= d).unwrap_or(false) {
+                return (cycles, true);
+            }
+        }
+    });
+
+    let state = emu.0.get_state();
+    let (exit_code, error) = match &state {
+        csx64::exec::State::Terminated(code) => (Some(*code), None),
+        csx64::exec::State::Error(err) => (None, Some(ExecErrorInfo::new(&emu, err))),
+        _ => (None, None),
+    };
+    let stdout = stdout.lock().unwrap().content.get_ref().clone();
+    let stderr = stderr.lock().unwrap().content.get_ref().clone();
+    Ok(CompletedRun { exit_code, stdout, stderr, cycles, state: state_name(&state), error, timed_out })
 }
 
 #[pymodule]
-fn csx64(_py: Python, m: &PyModule) -> PyResult<()> {
+fn csx64(py: Python, m: &PyModule) -> PyResult<()> {
+    let enums = PyModule::from_code(py, ENUMS_SRC, "csx64/enums.py", "csx64._enums")?;
+    for (name, cell) in [("State", &STATE_ENUM), ("StopKind", &STOP_KIND_ENUM)] {
+        let cls = enums.getattr(name)?;
+        m.add(name, cls)?;
+        let _ = cell.set(py, cls.into());
+    }
+
     m.add_class::<ObjectFile>()?;
+    m.add_class::<AsmWarning>()?;
+    m.add_class::<ListingLine>()?;
     m.add_class::<Executable>()?;
+    m.add_class::<Symbol>()?;
+    m.add_class::<LinkMap>()?;
+    m.add_class::<Archive>()?;
     m.add_class::<Emulator>()?;
     m.add_class::<MemoryFile>()?;
+    m.add_class::<EmulatorTask>()?;
+    m.add_class::<StopReason>()?;
+    m.add_class::<ExecErrorInfo>()?;
+    m.add_class::<BuildCache>()?;
+    m.add_class::<StdlibModule>()?;
+    m.add_class::<CompletedRun>()?;
+
+    m.add("AssembleError", py.get_type::<AssembleError>())?;
+    m.add("AsmSyntaxError", py.get_type::<AsmSyntaxError>())?;
+    m.add("UndefinedSymbolError", py.get_type::<UndefinedSymbolError>())?;
+    m.add("OperandSizeError", py.get_type::<OperandSizeError>())?;
+
+    m.add("LinkError", py.get_type::<LinkError>())?;
+    m.add("FormatError", py.get_type::<FormatError>())?;
+    m.add("ExecutionError", py.get_type::<ExecutionError>())?;
+    m.add("MemoryAccessError", py.get_type::<MemoryAccessError>())?;
+    m.add("InvalidOpcodeError", py.get_type::<InvalidOpcodeError>())?;
+    m.add("StackOverflowError", py.get_type::<StackOverflowError>())?;
+    m.add("SyscallError", py.get_type::<SyscallError>())?;
 
     m.add_function(wrap_pyfunction!(assemble, m)?)?;
+    m.add_function(wrap_pyfunction!(assemble_file, m)?)?;
+    m.add_function(wrap_pyfunction!(assemble_many, m)?)?;
+    m.add_function(wrap_pyfunction!(preprocess, m)?)?;
     m.add_function(wrap_pyfunction!(link, m)?)?;
     m.add_function(wrap_pyfunction!(stdlib, m)?)?;
+    m.add_function(wrap_pyfunction!(stdlib_modules, m)?)?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
     Ok(())
 }
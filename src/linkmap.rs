@@ -0,0 +1,95 @@
+//! Linker map reports: where each object's segments ended up, where each global symbol is, and the padding in between.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::debuginfo::{DebugInfo, LinkedObject};
+use crate::inspect::{self, Placement, Segment};
+
+/// One object's part of a segment.
+pub struct Contribution {
+    pub object: Arc<str>,
+    pub segment: Segment,
+    pub address: u64,
+    pub size: usize,
+}
+
+/// A run of bytes within a segment which no object contributed (alignment padding).
+pub struct Gap {
+    pub segment: Segment,
+    pub address: u64,
+    pub size: usize,
+}
+
+pub struct LinkMap {
+    /// The executable's segments as `(segment, address, size)`.
+    pub segments: Vec<(Segment, u64, usize)>,
+    pub contributions: Vec<Contribution>,
+    /// Global symbols as `(name, address, object)`, ordered by address.
+    pub symbols: Vec<(String, u64, Arc<str>)>,
+    pub gaps: Vec<Gap>,
+}
+
+impl LinkMap {
+    pub fn build(exe: &csx64::common::Executable, objs: &[LinkedObject], placements: &[Option<Placement>], info: &DebugInfo) -> Self {
+        let segments: Vec<_> = Segment::ALL.iter().map(|&seg| (seg, inspect::exe_segment_start(exe, seg), inspect::exe_segment_len(exe, seg))).collect();
+
+        let mut contributions = vec![];
+        for (linked, placement) in objs.iter().zip(placements) {
+            // objects the linker left out don't contribute anything
+            let placement = match placement {
+                Some(v) => v,
+                None => continue,
+            };
+            let object: Arc<str> = linked.name.into();
+            for seg in Segment::ALL {
+                let size = inspect::segment_len(linked.obj, seg);
+                if size != 0 {
+                    contributions.push(Contribution { object: object.clone(), segment: seg, address: placement[seg as usize], size });
+                }
+            }
+        }
+        contributions.sort_by_key(|c| (c.segment, c.address));
+
+        let mut gaps = vec![];
+        for &(seg, start, len) in segments.iter() {
+            let mut pos = start;
+            let placed = contributions.iter().filter(|c| c.segment == seg).map(|c| (c.address, c.size));
+            for (address, size) in placed.chain(std::iter::once((start + len as u64, 0))) {
+                if address > pos {
+                    gaps.push(Gap { segment: seg, address: pos, size: (address - pos) as usize });
+                }
+                pos = pos.max(address + size as u64);
+            }
+        }
+
+        let symbols = info.symbols().iter().filter(|s| s.global).map(|s| (s.name.clone(), s.address, s.object.clone())).collect();
+
+        LinkMap { segments, contributions, symbols, gaps }
+    }
+}
+
+impl fmt::Display for LinkMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "segments:")?;
+        for &(seg, address, size) in self.segments.iter() {
+            writeln!(f, "  {:<8}{:#010x}  {:#8x}", seg.name(), address, size)?;
+        }
+
+        writeln!(f, "contributions:")?;
+        for seg in Segment::ALL {
+            let mut items: Vec<(u64, usize, &str)> = self.contributions.iter().filter(|c| c.segment == seg).map(|c| (c.address, c.size, &*c.object)).collect();
+            items.extend(self.gaps.iter().filter(|g| g.segment == seg).map(|g| (g.address, g.size, "*padding*")));
+            items.sort_by_key(|&(address, _, _)| address);
+            for (address, size, object) in items {
+                writeln!(f, "  {:<8}{:#010x}  {:#8x}  {}", seg.name(), address, size, object)?;
+            }
+        }
+
+        writeln!(f, "symbols:")?;
+        for (name, address, object) in self.symbols.iter() {
+            writeln!(f, "  {:#010x}  {:<24}{}", address, name, object)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Source-level checks for code which assembles but is probably not what the author meant.
+//! The assembler itself only reports hard errors, so these run over the raw source text.
+
+use std::collections::HashSet;
+
+/// A single warning produced by [`lint`], with a 1-based line number.
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub line: usize,
+}
+
+/// Every code [`lint`] can produce.
+pub const CODES: &[&str] = &["unused-label", "unreachable-code", "data-in-text", "implicit-size"];
+
+const DATA_DIRECTIVES: &[&str] = &["db", "dw", "dd", "dq", "dx", "dy", "dz", "resb", "resw", "resd", "resq", "resx", "resy", "resz"];
+const SIZE_KEYWORDS: &[&str] = &["byte", "word", "dword", "qword", "xmmword", "ymmword", "zmmword", "tword"];
+
+/// Removes a trailing `;` comment, ignoring semicolons inside string literals.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '\'' || c == '"' || c == '`' => quote = Some(c),
+            None if c == ';' => return &line[..i],
+            None => (),
+        }
+    }
+    line
+}
+
+/// Splits a line into its (optional) label and the remaining instruction text.
+pub fn split_label(line: &str) -> (Option<&str>, &str) {
+    let line = line.trim();
+    let end = line.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(line.len());
+    if end != 0 && line[end..].starts_with(':') {
+        (Some(&line[..end]), line[end + 1..].trim())
+    } else {
+        (None, line)
+    }
+}
+
+/// Iterates over the identifiers in a piece of source text (string literals are skipped).
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    let mut quote = None;
+    let mut start = None;
+    let mut res = vec![];
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if let Some(q) = quote {
+            if c == q { quote = None; }
+            continue;
+        }
+        let ident = c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        match (start, ident) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                res.push(&text[s..i]);
+                start = None;
+            }
+            _ => (),
+        }
+        if c == '\'' || c == '"' || c == '`' { quote = Some(c); }
+    }
+    res.into_iter()
+}
+
+/// Runs all source checks over the given assembly source.
+pub fn lint(src: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = src.lines().map(strip_comment).collect();
+    let mut res = vec![];
+
+    // gather every identifier used outside of a label definition so we can spot unused labels
+    let mut used: HashSet<String> = HashSet::new();
+    let mut last_global = String::new();
+    for line in lines.iter() {
+        let (label, rest) = split_label(line);
+        if let Some(label) = label {
+            if !label.starts_with('.') { last_global = label.to_owned(); }
+        }
+        for ident in identifiers(rest) {
+            // local labels are referenced relative to the enclosing global label
+            if ident.starts_with('.') { used.insert(format!("{}{}", last_global, ident)); }
+            used.insert(ident.to_owned());
+        }
+    }
+
+    let mut segment = String::new();
+    let mut unreachable = false;
+    last_global.clear();
+    for (i, line) in lines.iter().enumerate() {
+        let line_num = i + 1;
+        let (label, rest) = split_label(line);
+
+        if let Some(label) = label {
+            unreachable = false;
+            let full = if label.starts_with('.') { format!("{}{}", last_global, label) } else { last_global = label.to_owned(); label.to_owned() };
+            let generated = label.starts_with(crate::preprocess::MACRO_LOCAL_PREFIX);
+            if !generated && !used.contains(&full) && !used.contains(label) {
+                res.push(Diagnostic { code: "unused-label", message: format!("label '{}' is never referenced", label), line: line_num });
+            }
+        }
+
+        let mut words = rest.split_whitespace();
+        let op = match words.next() {
+            Some(op) => op.to_ascii_lowercase(),
+            None => continue,
+        };
+        let operands = rest[op.len()..].trim();
+
+        match op.as_str() {
+            "segment" | "section" => {
+                segment = operands.trim_start_matches('.').to_ascii_lowercase();
+                unreachable = false;
+                continue;
+            }
+            "global" | "extern" | "align" | "static_assert" | "equ" => continue,
+            _ => (),
+        }
+        if words.next().map(|w| w.eq_ignore_ascii_case("equ")).unwrap_or(false) { continue; }
+
+        if unreachable {
+            res.push(Diagnostic { code: "unreachable-code", message: format!("'{}' can never be executed", op), line: line_num });
+            unreachable = false; // only report the first unreachable line of each run
+        }
+
+        if DATA_DIRECTIVES.contains(&op.as_str()) {
+            if segment == "text" {
+                res.push(Diagnostic { code: "data-in-text", message: format!("'{}' emits data into the text segment", op), line: line_num });
+            }
+            continue;
+        }
+
+        if operands.contains('[') && operands.contains(',') {
+            let lower = operands.to_ascii_lowercase();
+            let sized = identifiers(&lower).any(|w| SIZE_KEYWORDS.contains(&w));
+            let has_reg = operands.split(',').any(|o| !o.contains('[') && !o.trim().chars().next().map(|c| c.is_ascii_digit() || c == '-').unwrap_or(true));
+            if !sized && !has_reg {
+                res.push(Diagnostic { code: "implicit-size", message: format!("operand size of '{}' is implied rather than explicit", op), line: line_num });
+            }
+        }
+
+        if segment == "text" && matches!(op.as_str(), "ret" | "jmp" | "hlt" | "iret" | "iretq") {
+            unreachable = true;
+        }
+    }
+
+    res
+}
@@ -0,0 +1,120 @@
+//! Assembly listings: which bytes each source line emitted and where.
+//! The assembler does not track this itself, so we assemble a copy of the source with a marker label before every line
+//! and read the marker positions back out of the resulting object file.
+
+use std::collections::HashMap;
+
+use crate::inspect::{self, Segment};
+use crate::preprocess::Expanded;
+use crate::serial::{self, Reader, Writer};
+
+const ANCHOR: &str = "__listing";
+const MARKER: &str = "__lst_";
+
+pub struct Entry {
+    pub file: String,
+    pub line: usize,
+    pub segment: Option<Segment>,
+    pub offset: Option<usize>,
+    pub size: usize,
+    pub bytes: Vec<u8>,
+    /// Offset (within the segment) and size of each linker-patched hole starting on this line.
+    pub relocations: Vec<(usize, usize)>,
+    pub text: String,
+}
+impl Entry {
+    pub fn write(&self, w: &mut Writer) {
+        w.str(&self.file);
+        w.usize(self.line);
+        w.opt(self.segment, Writer::segment);
+        w.opt(self.offset, Writer::usize);
+        w.usize(self.size);
+        w.bytes(&self.bytes);
+        w.seq(&self.relocations, |w, &(offset, size)| { w.usize(offset); w.usize(size) });
+        w.str(&self.text);
+    }
+    pub fn read(r: &mut Reader) -> Result<Self, serial::Error> {
+        Ok(Entry {
+            file: r.str()?,
+            line: r.usize()?,
+            segment: r.opt(Reader::segment)?,
+            offset: r.opt(Reader::usize)?,
+            size: r.usize()?,
+            bytes: r.bytes()?.to_vec(),
+            relocations: r.seq(|r| Ok((r.usize()?, r.usize()?)))?,
+            text: r.str()?,
+        })
+    }
+}
+
+fn is_segment_directive(line: &str) -> bool {
+    let (_, rest) = crate::lint::split_label(crate::lint::strip_comment(line));
+    let word = rest.split_whitespace().next().unwrap_or("");
+    word.eq_ignore_ascii_case("segment") || word.eq_ignore_ascii_case("section")
+}
+
+/// Builds the listing for already-preprocessed source.
+/// Fails if the instrumented source doesn't assemble even though the original did (e.g. a marker label clashes with one of its symbols).
+pub fn build(asm_name: &str, src: &Expanded, asm_args: csx64::asm::Predefines) -> Result<Vec<Entry>, csx64::asm::AsmError> {
+    let lines: Vec<&str> = src.text.lines().collect();
+
+    let mut instrumented = String::with_capacity(src.text.len() * 2);
+    let mut in_segment = false;
+    for (i, line) in lines.iter().enumerate() {
+        if in_segment {
+            instrumented.push_str(&format!(".{}{}:\n", MARKER, i));
+        }
+        instrumented.push_str(line);
+        instrumented.push('\n');
+        if !in_segment && is_segment_directive(line) {
+            // local markers need an enclosing label
+            instrumented.push_str(&format!("{}:\n", ANCHOR));
+            in_segment = true;
+        }
+    }
+    let obj = csx64::asm::assemble(asm_name, &mut instrumented.as_bytes(), asm_args)?;
+
+    let mut positions: HashMap<usize, (Segment, usize)> = HashMap::new();
+    for (name, seg, offset) in inspect::labels(&obj) {
+        if let Some(index) = name.rsplit_once('.').and_then(|(_, local)| local.strip_prefix(MARKER)).and_then(|v| v.parse().ok()) {
+            positions.insert(index, (seg, offset));
+        }
+    }
+    let holes: HashMap<(Segment, usize), usize> = inspect::holes(&obj).map(|h| ((h.segment, h.offset), h.size)).collect();
+
+    // a line's bytes extend to the next marker in the same segment (or the end of the segment)
+    let mut ends = vec![0; lines.len()];
+    let mut next: HashMap<Segment, usize> = Segment::ALL.iter().map(|&seg| (seg, inspect::segment_len(&obj, seg))).collect();
+    for i in (0..lines.len()).rev() {
+        if let Some(&(seg, start)) = positions.get(&i) {
+            ends[i] = next[&seg];
+            next.insert(seg, start);
+        }
+    }
+
+    let mut res = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let origin = src.origin(i + 1);
+        let pos = positions.get(&i).copied();
+        let (size, bytes, relocations) = match pos {
+            None => (0, vec![], vec![]),
+            Some((seg, start)) => {
+                let end = ends[i].max(start);
+                let bytes = inspect::segment_content(&obj, seg).get(start..end).map(<[u8]>::to_vec).unwrap_or_default();
+                let relocations = (start..end).filter_map(|o| holes.get(&(seg, o)).map(|&size| (o, size))).collect();
+                (end - start, bytes, relocations)
+            }
+        };
+        res.push(Entry {
+            file: origin.file.to_string(),
+            line: origin.line,
+            segment: pos.map(|p| p.0),
+            offset: pos.map(|p| p.1),
+            size,
+            bytes,
+            relocations,
+            text: line.to_string(),
+        });
+    }
+    Ok(res)
+}
@@ -0,0 +1,605 @@
+//! Source transformations applied before handing text to `csx64::asm::assemble`.
+//! Every output line remembers where it came from so errors can point at the original file and line.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const MAX_INCLUDE_DEPTH: usize = 64;
+/// Largest file `%include` will read from disk.
+const MAX_INCLUDE_SIZE: u64 = 16 << 20;
+
+/// The file and (1-based) line an expanded line came from, plus the chain of `%include` sites which led there (outermost first).
+#[derive(Clone)]
+pub struct Origin {
+    pub file: Arc<str>,
+    pub line: usize,
+    pub chain: Arc<Vec<(Arc<str>, usize)>>,
+}
+
+/// Preprocessed source text along with the origin of each of its lines.
+pub struct Expanded {
+    pub text: String,
+    pub origins: Vec<Origin>,
+    root: Arc<str>,
+}
+impl Expanded {
+    fn new(root: Arc<str>, lines: Vec<Line>) -> Self {
+        let mut res = Expanded { text: String::new(), origins: Vec::with_capacity(lines.len()), root };
+        for (line, origin) in lines {
+            res.text.push_str(&line);
+            res.text.push('\n');
+            res.origins.push(origin);
+        }
+        res
+    }
+    /// Gets the origin of a 1-based line of the expanded text.
+    /// Lines past the end (e.g. errors reported at end of input) are attributed to the root file.
+    pub fn origin(&self, line: usize) -> Origin {
+        match line.checked_sub(1).and_then(|i| self.origins.get(i)) {
+            Some(v) => v.clone(),
+            None => Origin { file: self.root.clone(), line, chain: Default::default() },
+        }
+    }
+}
+
+pub struct Error {
+    pub message: String,
+    pub origin: Origin,
+}
+
+/// Where `%include` looks for files: virtual files first, then the including file's directory, then the search path.
+/// The filesystem is only searched if a search path was given (it may be empty), so by default only virtual files can be included.
+#[derive(Default)]
+pub struct IncludeResolver {
+    pub virtual_files: HashMap<String, String>,
+    pub search_path: Option<Vec<PathBuf>>,
+}
+impl IncludeResolver {
+    /// Gets the path and content of an included file, or `None` if there's no such file.
+    fn resolve(&self, name: &str, from: &str) -> Result<Option<(String, String)>, String> {
+        if let Some(content) = self.virtual_files.get(name) {
+            return Ok(Some((name.to_owned(), content.clone())));
+        }
+        let search_path = match &self.search_path {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        if Path::new(name).is_absolute() {
+            return Err(format!("include file '{}' must be a relative path", name));
+        }
+
+        let from_dir = match Path::new(from).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut escaped = None;
+        for root in std::iter::once(from_dir).chain(search_path.iter().map(PathBuf::as_path)) {
+            // resolve symlinks and `..` before checking the file is still under the directory it was looked up in
+            let (root, path) = match (root.canonicalize(), root.join(name).canonicalize()) {
+                (Ok(root), Ok(path)) => (root, path),
+                _ => continue,
+            };
+            if !path.starts_with(&root) {
+                escaped.get_or_insert(root);
+                continue;
+            }
+            if path.is_file() {
+                return read_limited(&path).map(|content| Some((path.to_string_lossy().into_owned(), content)));
+            }
+        }
+        match escaped {
+            Some(root) => Err(format!("include file '{}' is outside of {}", name, root.display())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads a text file, refusing ones larger than `MAX_INCLUDE_SIZE`.
+fn read_limited(path: &Path) -> Result<String, String> {
+    let fail = |e: std::io::Error| format!("failed to read include file '{}': {}", path.display(), e);
+    let mut content = String::new();
+    std::fs::File::open(path).map_err(fail)?.take(MAX_INCLUDE_SIZE + 1).read_to_string(&mut content).map_err(fail)?;
+    if content.len() as u64 > MAX_INCLUDE_SIZE {
+        return Err(format!("include file '{}' is larger than {} bytes", path.display(), MAX_INCLUDE_SIZE));
+    }
+    Ok(content)
+}
+
+/// Parses `%include "file"` (or `<file>` / `'file'`), returning the file name.
+fn parse_include(line: &str) -> Option<Result<&str, ()>> {
+    let rest = line.trim_start().strip_prefix("%include")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let rest = crate::lint::strip_comment(rest).trim();
+    let close = match rest.chars().next() {
+        Some('"') => '"',
+        Some('\'') => '\'',
+        Some('<') => '>',
+        _ => return Some(Err(())),
+    };
+    match rest[1..].strip_suffix(close) {
+        Some(name) if !name.is_empty() => Some(Ok(name)),
+        _ => Some(Err(())),
+    }
+}
+
+/// Checks that source bytes are UTF-8, reporting the line of the first invalid byte otherwise.
+pub fn decode<'a>(file: &str, src: &'a [u8]) -> Result<&'a str, Error> {
+    std::str::from_utf8(src).map_err(|e| {
+        let valid = &src[..e.valid_up_to()];
+        let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+        let message = format!("source is not valid UTF-8 (invalid byte at offset {})", e.valid_up_to());
+        Error { message, origin: Origin { file: file.into(), line, chain: Default::default() } }
+    })
+}
+
+/// Splits a file into lines tagged with their origin.
+fn file_lines(file: Arc<str>, src: &str, chain: Arc<Vec<(Arc<str>, usize)>>) -> Vec<Line> {
+    src.lines().enumerate().map(|(i, line)| (line.to_owned(), Origin { file: file.clone(), line: i + 1, chain: chain.clone() })).collect()
+}
+
+/// Reads the file included by the `%include` line at `origin`, returning its lines.
+fn include(line: &str, origin: &Origin, resolver: &IncludeResolver) -> Result<Vec<Line>, Error> {
+    let err = |message: String| Error { message, origin: origin.clone() };
+    let name = match parse_include(line) {
+        Some(Ok(name)) => name,
+        _ => return Err(err("expected a quoted file name after %include".into())),
+    };
+    if origin.chain.len() >= MAX_INCLUDE_DEPTH {
+        return Err(err(format!("include depth limit ({}) exceeded", MAX_INCLUDE_DEPTH)));
+    }
+    let (path, content) = match resolver.resolve(name, &origin.file) {
+        Ok(Some(v)) => v,
+        Ok(None) => return Err(err(format!("could not find include file '{}'", name))),
+        Err(message) => return Err(err(message)),
+    };
+    if *origin.file == *path || origin.chain.iter().any(|(f, _)| **f == *path) {
+        return Err(err(format!("'{}' includes itself", path)));
+    }
+
+    let mut chain = (*origin.chain).clone();
+    chain.push((origin.file.clone(), origin.line));
+    Ok(file_lines(path.into(), &content, Arc::new(chain)))
+}
+
+/// Recursively expands `%include` directives (without running the macro preprocessor).
+pub fn expand_includes(file: &str, src: &str, resolver: &IncludeResolver) -> Result<Expanded, Error> {
+    let root: Arc<str> = file.into();
+    let mut out = vec![];
+    expand_into(&mut out, file_lines(root.clone(), src, Default::default()), resolver)?;
+    Ok(Expanded::new(root, out))
+}
+fn expand_into(out: &mut Vec<Line>, lines: Vec<Line>, resolver: &IncludeResolver) -> Result<(), Error> {
+    for (line, origin) in lines {
+        if parse_include(&line).is_some() {
+            expand_into(out, include(&line, &origin, resolver)?, resolver)?;
+        } else {
+            push_line(out, line, origin)?;
+        }
+    }
+    Ok(())
+}
+
+/// Most lines the preprocessor will produce (`%rep` and recursive macros can otherwise grow without bound).
+const MAX_OUTPUT_LINES: usize = 1 << 20;
+
+/// Most steps (lines visited plus `%rep` iterations) the preprocessor will take. The output cap alone doesn't bound the work,
+/// since e.g. nested `%rep` blocks containing only `%define` produce no output.
+const MAX_WORK: usize = 1 << 24;
+
+fn push_line(out: &mut Vec<Line>, line: String, origin: Origin) -> Result<(), Error> {
+    if out.len() >= MAX_OUTPUT_LINES {
+        return Err(Error { message: format!("preprocessed output exceeds {} lines", MAX_OUTPUT_LINES), origin });
+    }
+    out.push((line, origin));
+    Ok(())
+}
+
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Prefix given to `%%` macro-local labels (made unique per expansion).
+/// These are local labels so that expanding a macro doesn't end the local label scope of the code around it.
+pub const MACRO_LOCAL_PREFIX: &str = ".__macro";
+
+type Line = (String, Origin);
+
+struct Macro {
+    params: usize,
+    body: Vec<Line>,
+}
+
+/// NASM-style macro preprocessor supporting `%define`, `%undef`, `%macro`, `%if`/`%ifdef`/`%ifndef`, `%rep`, and `%include`.
+pub struct Preprocessor<'a> {
+    defines: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    unique: usize,
+    includes: &'a IncludeResolver,
+    /// Steps taken so far (see `MAX_WORK`).
+    work: usize,
+}
+
+/// Splits a line into its `%directive` name (lowercased) and the rest of the line.
+fn directive(line: &str) -> Option<(String, &str)> {
+    let line = crate::lint::strip_comment(line).trim();
+    let rest = line.strip_prefix('%')?;
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    Some((rest[..end].to_ascii_lowercase(), rest[end..].trim()))
+}
+
+/// Finds the directive closing the block opened at `start`, along with any top-level `mids` (e.g. `%else`) along the way.
+fn find_block(lines: &[Line], start: usize, open: &[&str], close: &str, mids: &[&str]) -> Result<(Vec<usize>, usize), Error> {
+    let mut depth = 0;
+    let mut found = vec![];
+    for (i, (line, _)) in lines.iter().enumerate().skip(start + 1) {
+        let name = match directive(line) {
+            Some((name, _)) => name,
+            None => continue,
+        };
+        if open.contains(&name.as_str()) {
+            depth += 1;
+        } else if name == close {
+            if depth == 0 {
+                return Ok((found, i));
+            }
+            depth -= 1;
+        } else if depth == 0 && mids.contains(&name.as_str()) {
+            found.push(i);
+        }
+    }
+    Err(Error { message: format!("missing %{}", close), origin: lines[start].1.clone() })
+}
+
+/// Splits macro arguments on top-level commas (ignoring commas in strings and brackets).
+fn split_args(args: &str) -> Vec<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return vec![];
+    }
+    let mut res = vec![];
+    let (mut quote, mut depth, mut start) = (None, 0i32, 0);
+    for (i, c) in args.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '\'' | '"' | '`' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    res.push(args[start..i].trim().to_owned());
+                    start = i + 1;
+                }
+                _ => (),
+            },
+        }
+    }
+    res.push(args[start..].trim().to_owned());
+    res
+}
+
+/// Replaces whole identifiers (outside of string literals) using the given function.
+fn replace_identifiers(line: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut quote = None;
+    let mut start = None;
+    for (i, c) in line.char_indices().chain(std::iter::once((line.len(), '\0'))) {
+        if let Some(q) = quote {
+            if c == q { quote = None; }
+            if c != '\0' { res.push(c); }
+            continue;
+        }
+        let ident = c.is_ascii_alphanumeric() || c == '_';
+        match (start, ident) {
+            (None, true) => { start = Some(i); continue; }
+            (Some(_), true) => continue,
+            (Some(s), false) => {
+                let word = &line[s..i];
+                let numeric = word.starts_with(|c: char| c.is_ascii_digit());
+                match if numeric { None } else { f(word) } {
+                    Some(v) => res.push_str(&v),
+                    None => res.push_str(word),
+                }
+                start = None;
+            }
+            (None, false) => (),
+        }
+        if c == ';' {
+            res.push_str(&line[i..]);
+            return res;
+        }
+        if c == '\'' || c == '"' || c == '`' { quote = Some(c); }
+        if c != '\0' { res.push(c); }
+    }
+    res
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(defines: HashMap<String, String>, includes: &'a IncludeResolver) -> Self {
+        Preprocessor { defines, macros: Default::default(), unique: 0, includes, work: 0 }
+    }
+
+    /// Runs the preprocessor over a source file. Files are only included when their `%include` is reached,
+    /// so includes in untaken `%if` branches are never read.
+    pub fn run(&mut self, file: &str, src: &str) -> Result<Expanded, Error> {
+        let root: Arc<str> = file.into();
+        let mut out = vec![];
+        self.process(&file_lines(root.clone(), src, Default::default()), &mut out, 0)?;
+        Ok(Expanded::new(root, out))
+    }
+
+    fn substitute(&self, line: &str) -> String {
+        let mut line = line.to_owned();
+        for _ in 0..MAX_EXPANSION_DEPTH {
+            let mut changed = false;
+            let next = replace_identifiers(&line, |w| {
+                let v = self.defines.get(w).cloned();
+                changed |= v.is_some();
+                v
+            });
+            if !changed {
+                return next;
+            }
+            line = next;
+        }
+        line
+    }
+
+    fn eval(&self, expr: &str, origin: &Origin) -> Result<i64, Error> {
+        let expr = self.substitute(crate::lint::strip_comment(expr));
+        Expr::new(&expr).eval().map_err(|e| Error { message: format!("in %if expression '{}': {}", expr.trim(), e), origin: origin.clone() })
+    }
+
+    fn spend(&mut self, origin: &Origin) -> Result<(), Error> {
+        self.work += 1;
+        if self.work > MAX_WORK {
+            return Err(Error { message: format!("preprocessing exceeds the work limit ({} steps)", MAX_WORK), origin: origin.clone() });
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, lines: &[Line], out: &mut Vec<Line>, depth: usize) -> Result<(), Error> {
+        if depth > MAX_EXPANSION_DEPTH {
+            if let Some((_, origin)) = lines.first() {
+                return Err(Error { message: format!("macro expansion depth limit ({}) exceeded", MAX_EXPANSION_DEPTH), origin: origin.clone() });
+            }
+        }
+
+        let mut i = 0;
+        while i < lines.len() {
+            let (line, origin) = &lines[i];
+            let err = |message: String| Error { message, origin: origin.clone() };
+            self.spend(origin)?;
+
+            if let Some((name, rest)) = directive(line) {
+                match name.as_str() {
+                    "define" => {
+                        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                        let (key, value) = (&rest[..end], rest[end..].trim());
+                        if !crate::is_valid_symbol(key) {
+                            return Err(err(format!("'{}' is not a valid %define name", key)));
+                        }
+                        self.defines.insert(key.to_owned(), value.to_owned());
+                    }
+                    "undef" => { self.defines.remove(rest); }
+                    "macro" => {
+                        let mut parts = rest.split_whitespace();
+                        let (mname, params) = (parts.next().unwrap_or(""), parts.next().unwrap_or("0"));
+                        if !crate::is_valid_symbol(mname) {
+                            return Err(err(format!("'{}' is not a valid macro name", mname)));
+                        }
+                        let params = params.parse().map_err(|_| err(format!("invalid macro parameter count '{}'", params)))?;
+                        let (_, end) = find_block(lines, i, &["macro"], "endmacro", &[])?;
+                        self.macros.insert(mname.to_ascii_lowercase(), Macro { params, body: lines[i + 1..end].to_vec() });
+                        i = end;
+                    }
+                    "rep" => {
+                        let count = self.eval(rest, origin)?;
+                        if count < 0 {
+                            return Err(err(format!("%rep count must not be negative (got {})", count)));
+                        }
+                        if count as u64 > MAX_OUTPUT_LINES as u64 {
+                            return Err(err(format!("%rep count {} is too large", count)));
+                        }
+                        let (_, end) = find_block(lines, i, &["rep"], "endrep", &[])?;
+                        for _ in 0..count {
+                            self.spend(origin)?;
+                            self.process(&lines[i + 1..end], out, depth + 1)?;
+                        }
+                        i = end;
+                    }
+                    "if" | "ifdef" | "ifndef" => {
+                        let (mids, end) = find_block(lines, i, &["if", "ifdef", "ifndef"], "endif", &["elif", "else"])?;
+                        let mut branch_start = i;
+                        for branch_end in mids.iter().copied().chain(std::iter::once(end)) {
+                            let (kind, cond) = directive(&lines[branch_start].0).expect("block boundary is a directive");
+                            let taken = match kind.as_str() {
+                                "if" | "elif" => self.eval(cond, &lines[branch_start].1)? != 0,
+                                "ifdef" => self.defines.contains_key(cond),
+                                "ifndef" => !self.defines.contains_key(cond),
+                                _ => true, // else
+                            };
+                            if taken {
+                                self.process(&lines[branch_start + 1..branch_end], out, depth + 1)?;
+                                break;
+                            }
+                            branch_start = branch_end;
+                        }
+                        i = end;
+                    }
+                    "include" => {
+                        let included = include(line, origin, self.includes)?;
+                        self.process(&included, out, depth)?;
+                    }
+                    "elif" | "else" | "endif" | "endmacro" | "endrep" => return Err(err(format!("%{} without a matching opening directive", name))),
+                    _ => return Err(err(format!("unknown preprocessor directive '%{}'", name))),
+                }
+                i += 1;
+                continue;
+            }
+
+            let line = self.substitute(line);
+            let (label, rest) = crate::lint::split_label(&line);
+            let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let unique = self.unique + 1;
+            let body: Vec<Line> = match self.macros.get(&rest[..word_end].to_ascii_lowercase()) {
+                None => {
+                    push_line(out, line.clone(), origin.clone())?;
+                    i += 1;
+                    continue;
+                }
+                Some(mac) => {
+                    let args = split_args(crate::lint::strip_comment(&rest[word_end..]));
+                    if args.len() != mac.params {
+                        return Err(err(format!("macro '{}' expects {} argument(s) but got {}", &rest[..word_end], mac.params, args.len())));
+                    }
+                    mac.body.iter().map(|(body_line, _)| {
+                        let mut expanded = body_line.replace("%%", &format!("{}{}_", MACRO_LOCAL_PREFIX, unique)).replace("%0", &args.len().to_string());
+                        for (k, arg) in args.iter().enumerate().rev() {
+                            expanded = expanded.replace(&format!("%{}", k + 1), arg);
+                        }
+                        (expanded, origin.clone())
+                    }).collect()
+                }
+            };
+            self.unique = unique;
+            if let Some(label) = label {
+                push_line(out, format!("{}:", label), origin.clone())?;
+            }
+            self.process(&body, out, depth + 1)?;
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A tiny integer expression evaluator for `%if` and `%rep`.
+struct Expr<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Current nesting of unary operators and parentheses (see `MAX_DEPTH`).
+    depth: usize,
+}
+impl<'a> Expr<'a> {
+    /// Deepest nesting allowed, so pathological input is an error rather than a stack overflow.
+    const MAX_DEPTH: usize = 128;
+
+    fn new(src: &'a str) -> Self {
+        Expr { src, pos: 0, depth: 0 }
+    }
+    fn eval(mut self) -> Result<i64, String> {
+        let v = self.binary(0)?;
+        self.skip_ws();
+        if self.pos < self.src.len() {
+            return Err(format!("unexpected '{}'", &self.src[self.pos..]));
+        }
+        Ok(v)
+    }
+    fn skip_ws(&mut self) {
+        self.pos = self.src.len() - self.src[self.pos..].trim_start().len();
+    }
+    fn eat(&mut self, tok: &str) -> bool {
+        self.skip_ws();
+        if self.src[self.pos..].starts_with(tok) {
+            self.pos += tok.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Binary operators grouped by increasing precedence.
+    const LEVELS: &'static [&'static [&'static str]] = &[
+        &["||"], &["&&"], &["==", "!="], &["<=", ">=", "<", ">"], &["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"],
+    ];
+
+    /// Like `eat`, but refuses to match an operator which is just the start of a longer one (e.g. `<` in `<=`).
+    fn eat_op(&mut self, op: &str) -> bool {
+        self.skip_ws();
+        let rest = &self.src[self.pos..];
+        if !rest.starts_with(op) || Self::LEVELS.iter().flat_map(|l| l.iter()).any(|o| o.len() > op.len() && o.starts_with(op) && rest.starts_with(o)) {
+            return false;
+        }
+        self.pos += op.len();
+        true
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == Self::LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = Self::LEVELS[level].iter().find(|op| self.eat_op(op)) {
+            let rhs = self.binary(level + 1)?;
+            lhs = match *op {
+                "||" => ((lhs != 0) || (rhs != 0)) as i64,
+                "&&" => ((lhs != 0) && (rhs != 0)) as i64,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero".into()),
+                "/" => lhs.wrapping_div(rhs),
+                "%" => lhs.wrapping_rem(rhs),
+                _ => unreachable!(),
+            };
+        }
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.depth >= Self::MAX_DEPTH {
+            return Err(format!("expression is nested more than {} levels deep", Self::MAX_DEPTH));
+        }
+        self.depth += 1;
+        let res = self.unary_inner();
+        self.depth -= 1;
+        res
+    }
+    fn unary_inner(&mut self) -> Result<i64, String> {
+        if self.eat("-") { return Ok(self.unary()?.wrapping_neg()); }
+        if self.eat("~") { return Ok(!self.unary()?); }
+        if self.eat("!") { return Ok((self.unary()? == 0) as i64); }
+        if self.eat("+") { return self.unary(); }
+        if self.eat("(") {
+            let v = self.binary(0)?;
+            if !self.eat(")") { return Err("expected ')'".into()); }
+            return Ok(v);
+        }
+
+        self.skip_ws();
+        let rest = &self.src[self.pos..];
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let tok = &rest[..end];
+        self.pos += end;
+        let lower = tok.to_ascii_lowercase();
+        let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(bin) = lower.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2)
+        } else if let Some(oct) = lower.strip_prefix("0o") {
+            i64::from_str_radix(oct, 8)
+        } else if tok.starts_with(|c: char| c.is_ascii_digit()) {
+            tok.parse()
+        } else if tok.is_empty() {
+            return Err("expected a value".into());
+        } else {
+            return Err(format!("'{}' is not defined", tok));
+        };
+        parsed.map_err(|_| format!("invalid number '{}'", tok))
+    }
+}
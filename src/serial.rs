@@ -0,0 +1,234 @@
+//! Binary save/load for object files and executables.
+//! A file is a short header (magic, kind, format version), the upstream csx64 encoding of the value, and then the binding's
+//! own metadata (warnings, listings, debug info), which the upstream format has no room for.
+
+use std::fmt;
+
+use csx64::common::serialization::{BinaryRead, BinaryWrite};
+use sha2::{Digest, Sha256};
+
+use crate::inspect::Segment;
+
+const MAGIC: &[u8; 7] = b"pycsx64";
+/// Bumped whenever the layout of anything written here changes - older or newer files are rejected rather than misread.
+pub const VERSION: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Object,
+    Executable,
+    Archive,
+}
+impl Kind {
+    fn tag(self) -> u8 {
+        match self {
+            Kind::Object => b'o',
+            Kind::Executable => b'e',
+            Kind::Archive => b'a',
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Object => "an object file",
+            Kind::Executable => "an executable",
+            Kind::Archive => "an archive",
+        }
+    }
+}
+
+pub enum Error {
+    BadMagic,
+    WrongKind { expected: Kind, found: Kind },
+    Version(u32),
+    Truncated,
+    Corrupt(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "not a csx64 file (bad header)"),
+            Error::WrongKind { expected, found } => write!(f, "expected {} but found {}", expected.name(), found.name()),
+            Error::Version(v) => write!(f, "unsupported format version {} (this build reads version {})", v, VERSION),
+            Error::Truncated => write!(f, "data is truncated"),
+            Error::Corrupt(msg) => write!(f, "data is corrupt: {}", msg),
+        }
+    }
+}
+
+pub struct Writer(Vec<u8>);
+impl Writer {
+    pub fn u8(&mut self, v: u8) { self.0.push(v) }
+    pub fn u32(&mut self, v: u32) { self.0.extend_from_slice(&v.to_le_bytes()) }
+    pub fn u64(&mut self, v: u64) { self.0.extend_from_slice(&v.to_le_bytes()) }
+    pub fn usize(&mut self, v: usize) { self.u64(v as u64) }
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.usize(v.len());
+        self.0.extend_from_slice(v);
+    }
+    pub fn str(&mut self, v: &str) { self.bytes(v.as_bytes()) }
+    pub fn segment(&mut self, v: Segment) { self.u8(v as u8) }
+    pub fn opt<T>(&mut self, v: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        match v {
+            Some(v) => { self.u8(1); f(self, v) }
+            None => self.u8(0),
+        }
+    }
+    pub fn seq<T>(&mut self, v: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.usize(v.len());
+        for item in v {
+            f(self, item);
+        }
+    }
+}
+
+/// Most bytes `Reader::seq` allocates before it has read any items.
+const MAX_PREALLOC: usize = 64 << 10;
+
+pub struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(Error::Truncated);
+        }
+        let (res, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(res)
+    }
+    pub fn u8(&mut self) -> Result<u8, Error> { Ok(self.take(1)?[0]) }
+    pub fn u32(&mut self) -> Result<u32, Error> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    pub fn u64(&mut self) -> Result<u64, Error> { Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) }
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| Error::Corrupt("size out of range".into()))
+    }
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+    pub fn str(&mut self) -> Result<String, Error> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| Error::Corrupt("invalid utf-8 in string".into()))
+    }
+    pub fn segment(&mut self) -> Result<Segment, Error> {
+        let v = self.u8()?;
+        Segment::ALL.get(v as usize).copied().ok_or_else(|| Error::Corrupt(format!("invalid segment {}", v)))
+    }
+    pub fn opt<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<Option<T>, Error> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(f(self)?)),
+            v => Err(Error::Corrupt(format!("invalid option tag {}", v))),
+        }
+    }
+    pub fn seq<T>(&mut self, mut f: impl FnMut(&mut Self) -> Result<T, Error>) -> Result<Vec<T>, Error> {
+        let len = self.usize()?;
+        // don't trust the length for preallocation - every item takes at least one byte, and items can be much larger in memory
+        // than encoded (or nested), so cap the up-front allocation too and let the vec grow past it if the data is really there
+        let mut res = Vec::with_capacity(len.min(self.0.len()).min(MAX_PREALLOC / std::mem::size_of::<T>().max(1)));
+        for _ in 0..len {
+            res.push(f(self)?);
+        }
+        Ok(res)
+    }
+    /// Checks that everything has been read.
+    pub fn finish(self) -> Result<(), Error> {
+        match self.0.len() {
+            0 => Ok(()),
+            n => Err(Error::Corrupt(format!("{} bytes of trailing data", n))),
+        }
+    }
+}
+
+/// Encodes just the upstream representation of `value` (no header or metadata).
+pub fn payload<T: BinaryWrite>(value: &T) -> Vec<u8> {
+    let mut res = vec![];
+    value.bin_write(&mut res).expect("writing to memory cannot fail");
+    res
+}
+
+/// SHA-256 of the upstream representation of `value`.
+/// This ignores the binding's metadata, so e.g. the same source assembled with and without a listing gives the same digest.
+pub fn digest<T: BinaryWrite>(value: &T) -> [u8; 32] {
+    Sha256::digest(payload(value)).into()
+}
+/// SHA-256 of the fields written by `f`.
+pub fn digest_with(f: impl FnOnce(&mut Writer)) -> [u8; 32] {
+    let mut w = Writer(vec![]);
+    f(&mut w);
+    Sha256::digest(&w.0).into()
+}
+/// A python `__hash__` derived from a digest (never -1, which python reserves for errors).
+pub fn hash(digest: &[u8; 32]) -> isize {
+    match isize::from_le_bytes(digest[..std::mem::size_of::<isize>()].try_into().unwrap()) {
+        -1 => -2,
+        v => v,
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hashes the fields written by `f` (along with the binding and format versions) into a hex cache key.
+pub fn key(f: impl FnOnce(&mut Writer)) -> String {
+    let mut w = Writer(vec![]);
+    w.str(env!("CARGO_PKG_VERSION"));
+    w.u32(VERSION);
+    f(&mut w);
+    hex(&Sha256::digest(&w.0))
+}
+
+/// Encodes a complete file whose body is written by `body`.
+pub fn write_with(kind: Kind, body: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    let mut w = Writer(Vec::with_capacity(64));
+    w.0.extend_from_slice(MAGIC);
+    w.u8(kind.tag());
+    w.u32(VERSION);
+    body(&mut w);
+    w.0
+}
+/// Encodes a value and its metadata (written by `meta`) into a complete file.
+pub fn write<T: BinaryWrite>(kind: Kind, value: &T, meta: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    write_with(kind, |w| {
+        w.bytes(&payload(value));
+        meta(w);
+    })
+}
+
+/// Checks the header of a file, returning a reader positioned at the start of the body.
+pub fn read_header(kind: Kind, data: &[u8]) -> Result<Reader<'_>, Error> {
+    let mut r = Reader(data);
+    if r.take(MAGIC.len()).map_err(|_| Error::BadMagic)? != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let found = match r.u8()? {
+        b'o' => Kind::Object,
+        b'e' => Kind::Executable,
+        b'a' => Kind::Archive,
+        _ => return Err(Error::BadMagic),
+    };
+    if found != kind {
+        return Err(Error::WrongKind { expected: kind, found });
+    }
+    match r.u32()? {
+        VERSION => (),
+        v => return Err(Error::Version(v)),
+    }
+    Ok(r)
+}
+/// Decodes an upstream value written with [`payload`].
+pub fn read_payload<T: BinaryRead>(r: &mut Reader) -> Result<T, Error> {
+    let mut payload = r.bytes()?;
+    let value = T::bin_read(&mut payload).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::Truncated,
+        _ => Error::Corrupt(e.to_string()),
+    })?;
+    if !payload.is_empty() {
+        return Err(Error::Corrupt(format!("{} unused bytes after the csx64 data", payload.len())));
+    }
+    Ok(value)
+}
+/// Decodes the header and upstream value of a file, returning a reader positioned at the start of the metadata.
+pub fn read<T: BinaryRead>(kind: Kind, data: &[u8]) -> Result<(T, Reader<'_>), Error> {
+    let mut r = read_header(kind, data)?;
+    let value = read_payload(&mut r)?;
+    Ok((value, r))
+}
@@ -0,0 +1,56 @@
+//! Choosing which stdlib modules to link.
+//! Modules depend on each other through their externs, so selections are closed over those dependencies.
+
+use std::collections::HashMap;
+
+use csx64::asm::ObjectFile;
+
+use crate::inspect;
+
+/// For each module, the indices of the modules which define its externs.
+pub fn dependencies(modules: &[(&str, &ObjectFile)]) -> Vec<Vec<usize>> {
+    let mut definers: HashMap<&str, usize> = HashMap::new();
+    for (i, (_, obj)) in modules.iter().enumerate() {
+        for sym in inspect::global_symbols(obj) {
+            definers.entry(sym).or_insert(i);
+        }
+    }
+    modules.iter().map(|(_, obj)| {
+        let mut res: Vec<usize> = inspect::extern_symbols(obj).filter_map(|sym| definers.get(sym).copied()).collect();
+        res.sort_unstable();
+        res.dedup();
+        res
+    }).collect()
+}
+
+/// Finds the module `name` refers to, which is either the name of a module or a symbol it exports.
+fn resolve(modules: &[(&str, &ObjectFile)], name: &str) -> Result<usize, String> {
+    modules.iter().position(|(module, _)| *module == name)
+        .or_else(|| modules.iter().position(|(_, obj)| inspect::is_global(obj, name)))
+        .ok_or_else(|| format!("'{}' is not a stdlib module or function", name))
+}
+
+/// Selects modules to link: everything `include` names along with its dependencies (or every module if `include` is `None`),
+/// minus everything `exclude` names along with the modules which depend on it (as those could no longer be linked).
+pub fn select(modules: &[(&str, &ObjectFile)], include: Option<&[String]>, exclude: &[String]) -> Result<Vec<usize>, String> {
+    let deps = dependencies(modules);
+
+    let mut selected = vec![include.is_none(); modules.len()];
+    let mut stack = include.unwrap_or_default().iter().map(|name| resolve(modules, name)).collect::<Result<Vec<_>, _>>()?;
+    while let Some(i) = stack.pop() {
+        if !selected[i] {
+            selected[i] = true;
+            stack.extend_from_slice(&deps[i]);
+        }
+    }
+
+    let mut stack = exclude.iter().map(|name| resolve(modules, name)).collect::<Result<Vec<_>, _>>()?;
+    while let Some(i) = stack.pop() {
+        if selected[i] {
+            selected[i] = false;
+            stack.extend((0..modules.len()).filter(|&j| deps[j].contains(&i)));
+        }
+    }
+
+    Ok((0..modules.len()).filter(|&i| selected[i]).collect())
+}